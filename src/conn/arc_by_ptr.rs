@@ -0,0 +1,42 @@
+// Copyright (c) 2021 Anatoly Ikorsky
+//
+// Licensed under the Apache License, Version 2.0
+// <LICENSE-APACHE or http://www.apache.org/licenses/LICENSE-2.0> or the MIT
+// license <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. All files in the project carrying such notice may not be copied,
+// modified, or distributed except according to those terms.
+
+use std::{fmt, ops::Deref, sync::Arc};
+
+/// Shared value, that is compared by pointer.
+///
+/// Used to keep user-provided handlers in comparable options.
+pub(crate) struct ArcByPtr<T: ?Sized>(pub(crate) Arc<T>);
+
+impl<T: ?Sized> Clone for ArcByPtr<T> {
+    fn clone(&self) -> Self {
+        ArcByPtr(self.0.clone())
+    }
+}
+
+impl<T: ?Sized> PartialEq for ArcByPtr<T> {
+    fn eq(&self, other: &ArcByPtr<T>) -> bool {
+        Arc::as_ptr(&self.0) as *const u8 == Arc::as_ptr(&other.0) as *const u8
+    }
+}
+
+impl<T: ?Sized> Eq for ArcByPtr<T> {}
+
+impl<T: ?Sized + fmt::Debug> fmt::Debug for ArcByPtr<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl<T: ?Sized> Deref for ArcByPtr<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
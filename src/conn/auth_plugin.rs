@@ -0,0 +1,412 @@
+// Copyright (c) 2021 Anatoly Ikorsky
+//
+// Licensed under the Apache License, Version 2.0
+// <LICENSE-APACHE or http://www.apache.org/licenses/LICENSE-2.0> or the MIT
+// license <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. All files in the project carrying such notice may not be copied,
+// modified, or distributed except according to those terms.
+
+use mysql_common::crypto;
+
+use std::{fmt, io, sync::Arc};
+
+use crate::{DriverError, Result};
+
+/// Client side of an authentication plugin.
+///
+/// Handlers are registered via [`crate::OptsBuilder::auth_plugin_handler`] and are used
+/// whenever the server asks for a plugin, that is not natively supported by the driver
+/// (i.e. anything except `mysql_native_password`, `mysql_old_password`,
+/// `caching_sha2_password`, `sha256_password`, `mysql_clear_password`
+/// and `client_ed25519` if the `client-ed25519` feature is enabled).
+/// Registered handler takes precedence over the built-in `sha256_password`,
+/// `mysql_clear_password` and `client_ed25519` handlers.
+///
+/// The exchange goes as follows:
+///
+/// 1.  [`AuthPluginHandler::initial_response`] is called to get the data for the handshake
+///     response (or for the auth switch response, if the server asked to switch to this plugin);
+/// 2.  every "more data" packet sent by the server is passed to
+///     [`AuthPluginHandler::handle_more_data`] (the leading `0x01` byte is stripped).
+///     Returned bytes, if any, are sent back to the server;
+/// 3.  the exchange ends once the server sends an OK packet or an error.
+///
+/// ```rust
+/// # use mysql::{AuthPluginContext, AuthPluginHandler, OptsBuilder, Result};
+/// #[derive(Debug)]
+/// struct TokenAuth;
+///
+/// impl AuthPluginHandler for TokenAuth {
+///     fn name(&self) -> &str {
+///         "company_token_auth"
+///     }
+///
+///     fn initial_response(&self, ctx: &AuthPluginContext<'_>) -> Result<Vec<u8>> {
+///         let mut data = ctx.pass().unwrap_or_default().as_bytes().to_vec();
+///         data.push(0);
+///         Ok(data)
+///     }
+/// }
+///
+/// let opts = OptsBuilder::new().auth_plugin_handler(TokenAuth);
+/// ```
+pub trait AuthPluginHandler: fmt::Debug + Send + Sync + 'static {
+    /// Name of the plugin as sent by the server (e.g. `mysql_clear_password`).
+    fn name(&self) -> &str;
+
+    /// Returns the first auth response.
+    fn initial_response(&self, ctx: &AuthPluginContext<'_>) -> Result<Vec<u8>>;
+
+    /// Handles the "more data" packet sent by the server.
+    ///
+    /// `data` is the packet payload without the leading `0x01` byte. Returns the response
+    /// to send back to the server or `None` if nothing should be sent.
+    ///
+    /// Default implementation treats any "more data" packet as unexpected.
+    fn handle_more_data(
+        &self,
+        ctx: &AuthPluginContext<'_>,
+        data: &[u8],
+    ) -> Result<Option<Vec<u8>>> {
+        let _ = (ctx, data);
+        Err(DriverError::UnexpectedPacket.into())
+    }
+}
+
+/// Connection state available to an [`AuthPluginHandler`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AuthPluginContext<'a> {
+    user: Option<&'a str>,
+    pass: Option<&'a str>,
+    plugin_data: &'a [u8],
+    secure: bool,
+    server_public_key: Option<&'a [u8]>,
+}
+
+impl<'a> AuthPluginContext<'a> {
+    pub(crate) fn new(
+        user: Option<&'a str>,
+        pass: Option<&'a str>,
+        plugin_data: &'a [u8],
+        secure: bool,
+    ) -> Self {
+        Self {
+            user,
+            pass,
+            plugin_data,
+            secure,
+            server_public_key: None,
+        }
+    }
+
+    pub(crate) fn with_server_public_key(mut self, server_public_key: Option<&'a [u8]>) -> Self {
+        self.server_public_key = server_public_key;
+        self
+    }
+
+    /// User name.
+    pub fn user(&self) -> Option<&'a str> {
+        self.user
+    }
+
+    /// Password.
+    pub fn pass(&self) -> Option<&'a str> {
+        self.pass
+    }
+
+    /// Plugin data sent by the server (the scramble, for most plugins).
+    pub fn plugin_data(&self) -> &'a [u8] {
+        self.plugin_data
+    }
+
+    /// `true` if the connection is secured by TLS or is a socket connection,
+    /// i.e. it is safe to send sensitive data in clear text.
+    pub fn is_secure(&self) -> bool {
+        self.secure
+    }
+
+    /// Server RSA public key (in PEM) loaded from the
+    /// [`crate::Opts::get_server_public_key_path`], if any.
+    ///
+    /// The key is loaded only for the `sha256_password` plugin over an insecure connection.
+    pub fn server_public_key(&self) -> Option<&'a [u8]> {
+        self.server_public_key
+    }
+}
+
+/// Returns a built-in handler for the given plugin, if any.
+pub(crate) fn builtin_auth_plugin_handler(plugin_name: &str) -> Option<Arc<dyn AuthPluginHandler>> {
+    match plugin_name {
+        "sha256_password" => Some(Arc::new(Sha256PasswordHandler)),
+        "mysql_clear_password" => Some(Arc::new(ClearPasswordHandler)),
+        #[cfg(feature = "client-ed25519")]
+        "client_ed25519" => Some(Arc::new(Ed25519Handler)),
+        _ => None,
+    }
+}
+
+/// Returns the password followed by the zero terminator.
+fn null_terminated_pass(ctx: &AuthPluginContext<'_>) -> Vec<u8> {
+    let mut pass = ctx.pass().map(Vec::from).unwrap_or_else(Vec::new);
+    pass.push(0);
+    pass
+}
+
+/// Client side of the `sha256_password` plugin.
+///
+/// Password is sent in clear text over a secure connection. Otherwise the password
+/// is sent encrypted with the server public key, that is either loaded locally
+/// or requested from the server.
+#[derive(Debug)]
+pub(crate) struct Sha256PasswordHandler;
+
+impl Sha256PasswordHandler {
+    /// Request for the server public key.
+    const REQUEST_PUBLIC_KEY: u8 = 0x01;
+
+    fn encrypt_pass(ctx: &AuthPluginContext<'_>, key: &[u8]) -> Result<Vec<u8>> {
+        let nonce = ctx.plugin_data();
+        if nonce.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "sha256_password: server sent an empty scramble",
+            )
+            .into());
+        }
+        let mut pass = null_terminated_pass(ctx);
+        for (i, byte) in pass.iter_mut().enumerate() {
+            *byte ^= nonce[i % nonce.len()];
+        }
+        Ok(crypto::encrypt(&*pass, key))
+    }
+}
+
+impl AuthPluginHandler for Sha256PasswordHandler {
+    fn name(&self) -> &str {
+        "sha256_password"
+    }
+
+    fn initial_response(&self, ctx: &AuthPluginContext<'_>) -> Result<Vec<u8>> {
+        if ctx.is_secure() {
+            Ok(null_terminated_pass(ctx))
+        } else if ctx.pass().map(str::is_empty).unwrap_or(true) {
+            // empty password is sent as is
+            Ok(vec![0])
+        } else if let Some(key) = ctx.server_public_key() {
+            Self::encrypt_pass(ctx, key)
+        } else {
+            Ok(vec![Self::REQUEST_PUBLIC_KEY])
+        }
+    }
+
+    fn handle_more_data(&self, ctx: &AuthPluginContext<'_>, key: &[u8]) -> Result<Option<Vec<u8>>> {
+        if ctx.is_secure() || ctx.server_public_key().is_some() {
+            return Err(DriverError::UnexpectedPacket.into());
+        }
+
+        Self::encrypt_pass(ctx, key).map(Some)
+    }
+}
+
+/// Client side of the `mysql_clear_password` plugin.
+///
+/// Password is sent in clear text, so this plugin is refused over an insecure connection.
+#[derive(Debug)]
+pub(crate) struct ClearPasswordHandler;
+
+impl AuthPluginHandler for ClearPasswordHandler {
+    fn name(&self) -> &str {
+        "mysql_clear_password"
+    }
+
+    fn initial_response(&self, ctx: &AuthPluginContext<'_>) -> Result<Vec<u8>> {
+        if ctx.is_secure() {
+            Ok(null_terminated_pass(ctx))
+        } else {
+            Err(DriverError::ClearPasswordOverInsecureConnection.into())
+        }
+    }
+}
+
+/// Client side of the MariaDB `client_ed25519` plugin.
+///
+/// Scramble is signed using the Ed25519 key derived from the password.
+#[cfg(feature = "client-ed25519")]
+#[derive(Debug)]
+pub(crate) struct Ed25519Handler;
+
+#[cfg(feature = "client-ed25519")]
+impl Ed25519Handler {
+    /// Length of the scramble sent by the server.
+    const NONCE_LEN: usize = 32;
+
+    /// Signs the `message` the way MariaDB does it.
+    ///
+    /// It is the Ed25519 signature, except that the secret key is the SHA-512 hash
+    /// of the password rather than the hash of a 32-byte seed.
+    fn sign(pass: &[u8], message: &[u8]) -> [u8; 64] {
+        use curve25519_dalek::{constants::ED25519_BASEPOINT_TABLE, scalar::Scalar};
+        use sha2::{Digest, Sha512};
+
+        let mut az = [0_u8; 64];
+        az.copy_from_slice(&Sha512::digest(pass));
+        az[0] &= 248;
+        az[31] &= 63;
+        az[31] |= 64;
+
+        let mut a = [0_u8; 32];
+        a.copy_from_slice(&az[..32]);
+        let a = Scalar::from_bytes_mod_order(a);
+        let a_point = (&a * ED25519_BASEPOINT_TABLE).compress();
+
+        let r = Scalar::from_hash(Sha512::new().chain_update(&az[32..]).chain_update(message));
+        let r_point = (&r * ED25519_BASEPOINT_TABLE).compress();
+
+        let k = Scalar::from_hash(
+            Sha512::new()
+                .chain_update(r_point.as_bytes())
+                .chain_update(a_point.as_bytes())
+                .chain_update(message),
+        );
+        let s = k * a + r;
+
+        let mut signature = [0_u8; 64];
+        signature[..32].copy_from_slice(r_point.as_bytes());
+        signature[32..].copy_from_slice(s.as_bytes());
+        signature
+    }
+}
+
+#[cfg(feature = "client-ed25519")]
+impl AuthPluginHandler for Ed25519Handler {
+    fn name(&self) -> &str {
+        "client_ed25519"
+    }
+
+    fn initial_response(&self, ctx: &AuthPluginContext<'_>) -> Result<Vec<u8>> {
+        let nonce = ctx.plugin_data();
+        let nonce = &nonce[..std::cmp::min(nonce.len(), Self::NONCE_LEN)];
+        let pass = ctx.pass().unwrap_or_default().as_bytes();
+        Ok(Self::sign(pass, nonce).to_vec())
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod test {
+    use super::{builtin_auth_plugin_handler, AuthPluginContext};
+    use crate::{DriverError, Error};
+
+    const NONCE: &[u8] = b"0123456789abcdefghij";
+
+    pub(crate) const PUBLIC_KEY: &[u8] = b"-----BEGIN PUBLIC KEY-----
+MIIBIjANBgkqhkiG9w0BAQEFAAOCAQ8AMIIBCgKCAQEAyu92B/u5Lt4Fo8Ns4ITH
+4gNK/3L8rDwQMbTyWFWpGr+ms+dF479vyVWmq35dGhZ7tDZ/RwAnBhGogIJ33fWJ
+yDjDeLjINJPKetGVSRGopiPATSnOQ+06dw6+In5hIwRIvksprscsPRNchBYaST7W
+W5iXB1BabGhy7iO+AYSvJ8CFeKsSjegiHWkK/dI/XHlsgfsUpYtJTy7UToHn9vn+
+gEa88oGkl2speow4nY3MOIhqUDJc6BU4mQybVrsZDeHKvyB+kLkuePSjChHfJllk
+H0rgwojlxnA2HD3vJGkdXrt13c/Hm1tolsbYNa/yWTBW/PuQOrZ+Gy5p9QF5iRmz
+/wIDAQAB
+-----END PUBLIC KEY-----
+";
+
+    #[test]
+    fn should_send_sha256_password_over_secure_connection() {
+        let handler = builtin_auth_plugin_handler("sha256_password").unwrap();
+        let ctx = AuthPluginContext::new(Some("root"), Some("password"), NONCE, true);
+
+        assert_eq!(handler.initial_response(&ctx).unwrap(), b"password\0");
+    }
+
+    #[test]
+    fn should_encrypt_sha256_password_over_insecure_connection() {
+        let handler = builtin_auth_plugin_handler("sha256_password").unwrap();
+        let ctx = AuthPluginContext::new(Some("root"), Some("password"), NONCE, false);
+
+        // client requests the public key
+        assert_eq!(handler.initial_response(&ctx).unwrap(), vec![0x01]);
+
+        // server sends the key in the "more data" packet
+        let encrypted = handler.handle_more_data(&ctx, PUBLIC_KEY).unwrap().unwrap();
+        assert_eq!(encrypted.len(), 256);
+        assert_ne!(&encrypted[..9], b"password\0");
+    }
+
+    #[test]
+    fn should_use_local_server_public_key_for_sha256_password() {
+        let handler = builtin_auth_plugin_handler("sha256_password").unwrap();
+        let ctx = AuthPluginContext::new(Some("root"), Some("password"), NONCE, false)
+            .with_server_public_key(Some(PUBLIC_KEY));
+
+        // no public key request round-trip
+        let encrypted = handler.initial_response(&ctx).unwrap();
+        assert_eq!(encrypted.len(), 256);
+        assert!(matches!(
+            handler.handle_more_data(&ctx, PUBLIC_KEY),
+            Err(Error::DriverError(DriverError::UnexpectedPacket))
+        ));
+    }
+
+    #[test]
+    fn should_refuse_to_encrypt_sha256_password_with_empty_scramble() {
+        let handler = builtin_auth_plugin_handler("sha256_password").unwrap();
+        let ctx = AuthPluginContext::new(Some("root"), Some("password"), &[], false);
+
+        assert!(matches!(
+            handler.handle_more_data(&ctx, PUBLIC_KEY),
+            Err(Error::IoError(_))
+        ));
+    }
+
+    #[test]
+    fn should_send_empty_sha256_password_as_is() {
+        let handler = builtin_auth_plugin_handler("sha256_password").unwrap();
+        let ctx = AuthPluginContext::new(Some("root"), None, NONCE, false);
+
+        assert_eq!(handler.initial_response(&ctx).unwrap(), vec![0x00]);
+    }
+
+    #[test]
+    fn should_send_clear_password_over_secure_connection() {
+        let handler = builtin_auth_plugin_handler("mysql_clear_password").unwrap();
+        let ctx = AuthPluginContext::new(Some("root"), Some("password"), NONCE, true);
+
+        assert_eq!(handler.initial_response(&ctx).unwrap(), b"password\0");
+        assert!(matches!(
+            handler.handle_more_data(&ctx, b"foo"),
+            Err(Error::DriverError(DriverError::UnexpectedPacket))
+        ));
+    }
+
+    #[test]
+    fn should_refuse_clear_password_over_insecure_connection() {
+        let handler = builtin_auth_plugin_handler("mysql_clear_password").unwrap();
+        let ctx = AuthPluginContext::new(Some("root"), Some("password"), NONCE, false);
+
+        assert!(matches!(
+            handler.initial_response(&ctx),
+            Err(Error::DriverError(
+                DriverError::ClearPasswordOverInsecureConnection
+            ))
+        ));
+    }
+
+    #[test]
+    #[cfg(feature = "client-ed25519")]
+    fn should_sign_client_ed25519_scramble() {
+        let handler = builtin_auth_plugin_handler("client_ed25519").unwrap();
+        let nonce = (0_u8..32).collect::<Vec<_>>();
+        let ctx = AuthPluginContext::new(Some("root"), Some("secret"), &nonce, false);
+
+        let signature = handler.initial_response(&ctx).unwrap();
+        assert_eq!(
+            signature,
+            [
+                0x54, 0x6c, 0x3e, 0x93, 0x11, 0xd1, 0x9e, 0xe3, 0x89, 0xd1, 0xae, 0x5d, 0x94, 0x58,
+                0x21, 0xbf, 0xe4, 0x59, 0x42, 0x39, 0x10, 0x13, 0x6f, 0x16, 0xb3, 0xbd, 0x2a, 0xe8,
+                0x9f, 0xd7, 0xd6, 0x3b, 0x44, 0x9e, 0x68, 0x9f, 0x4b, 0xe8, 0x12, 0x89, 0x1a, 0x85,
+                0xb8, 0xf9, 0x99, 0x07, 0x64, 0x46, 0xd9, 0x2a, 0xdb, 0x83, 0xd0, 0x70, 0x72, 0x15,
+                0xaa, 0xa6, 0x0b, 0x5a, 0x23, 0x22, 0x09, 0x07,
+            ]
+        );
+    }
+}
@@ -0,0 +1,94 @@
+// Copyright (c) 2021 Anatoly Ikorsky
+//
+// Licensed under the Apache License, Version 2.0
+// <LICENSE-APACHE or http://www.apache.org/licenses/LICENSE-2.0> or the MIT
+// license <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. All files in the project carrying such notice may not be copied,
+// modified, or distributed except according to those terms.
+
+#[cfg(not(target_os = "wasi"))]
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use crate::{prelude::*, Conn, Opts, Result};
+
+/// Handle, that cancels work of a connection from another thread
+/// (see [`Conn::cancel_handle`]).
+///
+/// Every call opens a side connection to the same server and issues the corresponding
+/// `KILL` statement, so the user needs the privilege to kill the connection's threads
+/// (e.g. the same user or `CONNECTION_ADMIN`).
+///
+/// ```rust
+/// # mysql::doctest_wrapper!(__result, {
+/// # use mysql::*;
+/// # use mysql::prelude::*;
+/// let mut conn = Conn::new(get_opts())?;
+/// let handle = conn.cancel_handle();
+///
+/// let canceller = std::thread::spawn(move || {
+///     std::thread::sleep(std::time::Duration::from_millis(500));
+///     handle.kill_query()
+/// });
+///
+/// // `SLEEP` returns `1` if interrupted
+/// assert_eq!(conn.query_first::<u8, _>("SELECT SLEEP(10)")?, Some(1));
+/// canceller.join().unwrap()?;
+/// # });
+/// ```
+#[derive(Debug, Clone)]
+pub struct CancelHandle {
+    opts: Opts,
+    connection_id: u32,
+}
+
+impl CancelHandle {
+    pub(crate) fn new(opts: Opts, connection_id: u32) -> Self {
+        CancelHandle {
+            opts,
+            connection_id,
+        }
+    }
+
+    /// Identifier of the connection this handle cancels.
+    pub fn connection_id(&self) -> u32 {
+        self.connection_id
+    }
+
+    /// Terminates the statement, that the connection is currently executing
+    /// (`KILL QUERY <connection_id>`), leaving the connection itself intact.
+    ///
+    /// An interrupted statement either fails with the `ER_QUERY_INTERRUPTED` server error
+    /// or returns early (e.g. `SLEEP` returns `1`). Does nothing if the connection is idle.
+    pub fn kill_query(&self) -> Result<()> {
+        self.kill("QUERY")
+    }
+
+    /// Terminates the connection (`KILL CONNECTION <connection_id>`).
+    ///
+    /// The connection will fail with an IO error on the next use.
+    pub fn kill_connection(&self) -> Result<()> {
+        self.kill("CONNECTION")
+    }
+
+    /// Same as [`CancelHandle::kill_query`], but does nothing once `done` is set
+    /// (it is checked before connecting and before killing).
+    ///
+    /// Returns `true` if the query was killed.
+    #[cfg(not(target_os = "wasi"))]
+    pub(crate) fn kill_query_unless(&self, done: &AtomicBool) -> Result<bool> {
+        if done.load(Ordering::SeqCst) {
+            return Ok(false);
+        }
+        let mut conn = Conn::new(self.opts.clone())?;
+        if done.load(Ordering::SeqCst) {
+            return Ok(false);
+        }
+        conn.query_drop(format!("KILL QUERY {}", self.connection_id))?;
+        Ok(true)
+    }
+
+    fn kill(&self, kind: &str) -> Result<()> {
+        let mut conn = Conn::new(self.opts.clone())?;
+        conn.query_drop(format!("KILL {} {}", kind, self.connection_id))
+    }
+}
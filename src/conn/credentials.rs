@@ -0,0 +1,81 @@
+// Copyright (c) 2021 Anatoly Ikorsky
+//
+// Licensed under the Apache License, Version 2.0
+// <LICENSE-APACHE or http://www.apache.org/licenses/LICENSE-2.0> or the MIT
+// license <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. All files in the project carrying such notice may not be copied,
+// modified, or distributed except according to those terms.
+
+use std::fmt;
+
+use crate::error::CredentialsError;
+
+/// Source of credentials, that is queried at every connect.
+///
+/// Provider is registered via [`crate::OptsBuilder::credentials_provider`]. It is called
+/// during the handshake of every new connection (including connections created by a pool
+/// and connections re-established by [`crate::Conn::reset`]), so it is the way to use
+/// rotated passwords or short-lived auth tokens.
+///
+/// ```rust
+/// # use mysql::{Credentials, CredentialsError, CredentialsProvider, OptsBuilder};
+/// #[derive(Debug)]
+/// struct EnvCredentials;
+///
+/// impl CredentialsProvider for EnvCredentials {
+///     fn get_credentials(&self) -> Result<Credentials, CredentialsError> {
+///         let pass = std::env::var("DB_TOKEN").map_err(CredentialsError::new)?;
+///         Ok(Credentials::new(None, Some(pass)))
+///     }
+/// }
+///
+/// let opts = OptsBuilder::new()
+///     .user(Some("app"))
+///     .credentials_provider(EnvCredentials);
+/// ```
+pub trait CredentialsProvider: fmt::Debug + Send + Sync + 'static {
+    /// Returns credentials for a new connection.
+    fn get_credentials(&self) -> Result<Credentials, CredentialsError>;
+}
+
+/// Credentials returned by a [`CredentialsProvider`].
+#[derive(Clone, Eq, PartialEq, Default)]
+pub struct Credentials {
+    user: Option<String>,
+    pass: Option<String>,
+}
+
+impl Credentials {
+    /// Creates new credentials.
+    ///
+    /// `None` user means that the user defined in `Opts` will be used.
+    pub fn new<T, U>(user: Option<T>, pass: Option<U>) -> Self
+    where
+        T: Into<String>,
+        U: Into<String>,
+    {
+        Self {
+            user: user.map(Into::into),
+            pass: pass.map(Into::into),
+        }
+    }
+
+    /// User name.
+    pub fn user(&self) -> Option<&str> {
+        self.user.as_deref()
+    }
+
+    /// Password.
+    pub fn pass(&self) -> Option<&str> {
+        self.pass.as_deref()
+    }
+}
+
+impl fmt::Debug for Credentials {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Credentials")
+            .field("user", &self.user)
+            .field("pass", &self.pass.as_ref().map(|_| "..."))
+            .finish()
+    }
+}
@@ -0,0 +1,154 @@
+// Copyright (c) 2021 Anatoly Ikorsky
+//
+// Licensed under the Apache License, Version 2.0
+// <LICENSE-APACHE or http://www.apache.org/licenses/LICENSE-2.0> or the MIT
+// license <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. All files in the project carrying such notice may not be copied,
+// modified, or distributed except according to those terms.
+
+use mysql_common::packets::OkPacket;
+
+use std::{cmp, sync::Arc};
+
+use crate::{conn::query_result::Or, consts::StatusFlags, Column, Conn, Result, Row};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum CursorState {
+    /// The next batch of rows needs to be fetched.
+    Fetch,
+    /// Iterator is in the middle of a fetched batch.
+    InBatch,
+    /// Server refused to open a cursor, so rows are streamed as usual.
+    Streaming,
+    /// No more rows.
+    Done,
+}
+
+/// Server-side cursor over a statement result set (see [`Conn::exec_cursor`]).
+///
+/// It is an iterator over rows. Rows are fetched from the server in batches.
+/// The cursor is closed on drop.
+#[derive(Debug)]
+pub struct Cursor<'a> {
+    conn: &'a mut Conn,
+    stmt_id: u32,
+    columns: Arc<[Column]>,
+    fetch_size: u32,
+    state: CursorState,
+    /// `true` if the server-side cursor is not yet exhausted.
+    is_open: bool,
+}
+
+impl<'a> Cursor<'a> {
+    pub(crate) fn new(
+        conn: &'a mut Conn,
+        stmt_id: u32,
+        meta: Or<Vec<Column>, OkPacket<'static>>,
+        fetch_size: u32,
+    ) -> Cursor<'a> {
+        let (columns, state) = match meta {
+            Or::A(columns) => {
+                let state = if conn
+                    .0
+                    .status_flags
+                    .contains(StatusFlags::SERVER_STATUS_CURSOR_EXISTS)
+                {
+                    // rows aren't sent until requested
+                    conn.0.has_results = false;
+                    CursorState::Fetch
+                } else {
+                    CursorState::Streaming
+                };
+                (columns.into(), state)
+            }
+            Or::B(_) => (Vec::new().into(), CursorState::Done),
+        };
+        Cursor {
+            conn,
+            stmt_id,
+            columns,
+            fetch_size: cmp::max(fetch_size, 1),
+            is_open: state == CursorState::Fetch,
+            state,
+        }
+    }
+
+    /// Returns columns of the result set.
+    pub fn columns(&self) -> &[Column] {
+        &self.columns
+    }
+
+    /// Discards the rest of the response (if any) and closes the server-side cursor.
+    fn close(&mut self) -> Result<()> {
+        if let CursorState::InBatch | CursorState::Streaming = self.state {
+            while self.conn.next_bin(self.columns.clone())?.is_some() {}
+        }
+        if self.state == CursorState::Streaming {
+            // other result sets are discarded
+            while self.conn.more_results_exists() {
+                if let Or::A(columns) = self.conn.handle_result_set()? {
+                    let columns: Arc<[Column]> = columns.into();
+                    while self.conn.next_bin(columns.clone())?.is_some() {}
+                }
+            }
+        }
+        self.state = CursorState::Done;
+        if self.is_open {
+            self.is_open = false;
+            self.conn.reset_stmt(self.stmt_id)?;
+        }
+        Ok(())
+    }
+}
+
+impl Iterator for Cursor<'_> {
+    type Item = Result<Row>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            match self.state {
+                CursorState::Done => return None,
+                CursorState::Fetch => {
+                    if let Err(err) = self.conn.fetch_rows(self.stmt_id, self.fetch_size) {
+                        self.state = CursorState::Done;
+                        return Some(Err(err));
+                    }
+                    self.state = CursorState::InBatch;
+                }
+                CursorState::InBatch | CursorState::Streaming => {
+                    match self.conn.next_bin(self.columns.clone()) {
+                        Ok(Some(row)) => return Some(Ok(row)),
+                        Ok(None) if self.state == CursorState::InBatch => {
+                            let last_row_sent = self
+                                .conn
+                                .0
+                                .status_flags
+                                .contains(StatusFlags::SERVER_STATUS_LAST_ROW_SENT);
+                            if last_row_sent {
+                                self.is_open = false;
+                                self.state = CursorState::Done;
+                            } else {
+                                self.state = CursorState::Fetch;
+                            }
+                        }
+                        Ok(None) => {
+                            if let Err(err) = self.close() {
+                                return Some(Err(err));
+                            }
+                        }
+                        Err(err) => {
+                            self.state = CursorState::Done;
+                            return Some(Err(err));
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl Drop for Cursor<'_> {
+    fn drop(&mut self) {
+        let _ = self.close();
+    }
+}
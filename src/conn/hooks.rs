@@ -0,0 +1,81 @@
+// Copyright (c) 2021 Anatoly Ikorsky
+//
+// Licensed under the Apache License, Version 2.0
+// <LICENSE-APACHE or http://www.apache.org/licenses/LICENSE-2.0> or the MIT
+// license <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. All files in the project carrying such notice may not be copied,
+// modified, or distributed except according to those terms.
+
+use std::fmt;
+
+use crate::{Conn, Result};
+
+/// Connection lifecycle hooks.
+///
+/// Hooks are registered via [`crate::OptsBuilder::hooks`]. Every method has a default
+/// implementation, so only the interesting ones need to be implemented. Note that hooks
+/// may be called concurrently for different connections of a pool.
+///
+/// ```rust
+/// # mysql::doctest_wrapper!(__result, {
+/// # use mysql::*;
+/// # use mysql::prelude::*;
+/// #[derive(Debug)]
+/// struct SessionSetup {
+///     time_zone: String,
+/// }
+///
+/// impl ConnHooks for SessionSetup {
+///     fn after_connect(&self, conn: &mut Conn) -> Result<()> {
+///         conn.exec_drop("SET time_zone = ?", (self.time_zone.as_str(),))
+///     }
+///
+///     fn after_release(&self, conn: &mut Conn) -> bool {
+///         // do not reuse a connection, that went bad
+///         conn.ping()
+///     }
+/// }
+///
+/// let opts = OptsBuilder::from_opts(get_opts()).hooks(SessionSetup {
+///     time_zone: "+00:00".into(),
+/// });
+/// let pool = Pool::new(opts)?;
+/// # });
+/// ```
+pub trait ConnHooks: fmt::Debug + Send + Sync + 'static {
+    /// Called once a new connection is established and `Opts::get_init` commands
+    /// are executed.
+    ///
+    /// Also called after a pool resets a connection (see `PoolOpts::with_reset_connection`).
+    /// An error will be returned from the corresponding `Conn::new` or `Pool::get_conn` call.
+    fn after_connect(&self, conn: &mut Conn) -> Result<()> {
+        let _ = conn;
+        Ok(())
+    }
+
+    /// Called before a pool hands out a connection.
+    ///
+    /// Returning `false` will close the connection, and the pool will try another one.
+    /// Checkout fails with `DriverError::AcquireVetoed` after 10 vetoed connections
+    /// (or with `DriverError::Timeout` once the checkout timeout, if any, is exceeded).
+    fn before_acquire(&self, conn: &mut Conn) -> bool {
+        let _ = conn;
+        true
+    }
+
+    /// Called when a connection is returned to a pool (after the cleanup, if any).
+    ///
+    /// Returning `false` will close the connection instead of returning it to the pool.
+    fn after_release(&self, conn: &mut Conn) -> bool {
+        let _ = conn;
+        true
+    }
+
+    /// Called before a connection is closed (i.e. when `Conn` is dropped).
+    ///
+    /// Note that it is also called for connections, that failed to initialize (e.g. if
+    /// `after_connect` returned an error).
+    fn on_close(&self, conn: &mut Conn) {
+        let _ = conn;
+    }
+}
@@ -17,7 +17,7 @@ use mysql_common::{
         binlog_request::BinlogRequest, AuthPlugin, AuthSwitchRequest, Column, ComStmtClose,
         ComStmtExecuteRequestBuilder, ComStmtSendLongData, CommonOkPacket, ErrPacket,
         HandshakePacket, HandshakeResponse, OkPacket, OkPacketDeserializer, OkPacketKind,
-        OldAuthSwitchRequest, ResultSetTerminator, SessionStateInfo,
+        OldAuthSwitchRequest, ProgressReport, ResultSetTerminator, SessionStateInfo,
     },
     proto::{codec::Compression, sync_framed::MySyncFramed, MySerialize},
     row::{Row, RowDeserializer},
@@ -29,8 +29,6 @@ use mysql_common::{
     packets::SslRequest,
 };
 
-#[cfg(not(target_os = "wasi"))]
-use std::process;
 use std::{
     borrow::{Borrow, Cow},
     cmp,
@@ -40,6 +38,16 @@ use std::{
     mem,
     ops::{Deref, DerefMut},
     sync::Arc,
+    thread,
+    time::{Duration, Instant},
+};
+#[cfg(not(target_os = "wasi"))]
+use std::{
+    process,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::{sync_channel, RecvTimeoutError},
+    },
 };
 
 #[cfg(unix)]
@@ -48,24 +56,34 @@ use std::os::unix::io::{AsRawFd, RawFd};
 use crate::{
     buffer_pool::{get_buffer, Buffer},
     conn::{
+        auth_plugin::{builtin_auth_plugin_handler, AuthPluginContext, AuthPluginHandler},
+        cancel::CancelHandle,
+        cursor::Cursor,
         local_infile::LocalInfile,
+        opts::host_is_loopback,
         pool::{Pool, PooledConn},
+        query_attributes::{
+            put_query_attributes, ComStmtExecuteWithAttributes, WithQueryAttributes,
+        },
         query_result::{Binary, Or, Text},
+        session_state::{track_session_state_query, SessionState},
         stmt::{InnerStmt, Statement},
         stmt_cache::StmtCache,
-        transaction::{AccessMode, TxOpts},
+        transaction::{AccessMode, RetryPolicy, TxOpts},
     },
-    consts::{CapabilityFlags, Command, StatusFlags, MAX_PAYLOAD_LEN},
+    consts::{CapabilityFlags, Command, CursorType, StatusFlags, MAX_PAYLOAD_LEN},
     from_value, from_value_opt,
     io::Stream,
     prelude::*,
     DriverError::{
-        MismatchedStmtParams, NamedParamsForPositionalQuery, OldMysqlPasswordDisabled,
-        Protocol41NotSet, ReadOnlyTransNotSupported, SetupError, UnexpectedPacket,
+        ConnectTimeout, CouldNotConnect, InvalidServerPublicKey, MismatchedStmtParams,
+        NamedParamsForPositionalQuery, OldMysqlPasswordDisabled, Protocol41NotSet,
+        ReadOnlyTransNotSupported, ServerPublicKeyMismatch, SetupError, UnexpectedPacket,
         UnknownAuthPlugin, UnsupportedProtocol,
     },
     Error::{self, DriverError, MySqlError},
-    LocalInfileHandler, Opts, OptsBuilder, Params, QueryResult, Result, Transaction,
+    LocalInfileHandler, Opts, OptsBuilder, Params, ProgressHandler, QueryResult, Result,
+    Transaction,
     Value::{self, Bytes, NULL},
 };
 
@@ -74,13 +92,23 @@ use crate::SslOpts;
 
 use self::binlog_stream::BinlogStream;
 
+mod arc_by_ptr;
+pub mod auth_plugin;
 pub mod binlog_stream;
+pub mod cancel;
+pub mod credentials;
+pub mod cursor;
+pub mod hooks;
 pub mod local_infile;
 pub mod opts;
 pub mod pool;
+pub mod progress;
 pub mod query;
+pub mod query_attributes;
 pub mod query_result;
 pub mod queryable;
+pub mod replicated_pool;
+pub mod session_state;
 pub mod stmt;
 mod stmt_cache;
 pub mod transaction;
@@ -177,6 +205,15 @@ struct ConnInner {
     connected: bool,
     has_results: bool,
     local_infile_handler: Option<LocalInfileHandler>,
+    progress_handler: Option<ProgressHandler>,
+    /// Server this connection is connected to (`None` for socket connections).
+    connected_host: Option<(url::Host, u16)>,
+    /// When the underlying stream was established.
+    connected_at: Instant,
+    /// Attributes sent with every query (see `Conn::with_query_attributes`).
+    query_attributes: Vec<(String, Value)>,
+    /// Session state accumulated from OK packets.
+    session_state: SessionState,
 }
 
 impl ConnInner {
@@ -196,10 +233,24 @@ impl ConnInner {
             server_version: None,
             mariadb_server_version: None,
             local_infile_handler: None,
+            progress_handler: None,
+            connected_host: None,
+            connected_at: Instant::now(),
+            query_attributes: Vec::new(),
+            session_state: SessionState::default(),
         }
     }
 }
 
+/// Result of [`Conn::wait_for_gtid_set`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum GtidWaitResult {
+    /// The GTID set was applied on the server.
+    Reached,
+    /// The timeout expired before the GTID set was applied.
+    TimedOut,
+}
+
 /// Mysql connection.
 #[derive(Debug)]
 pub struct Conn(Box<ConnInner>);
@@ -218,6 +269,17 @@ impl Conn {
         self.0.connection_id
     }
 
+    /// Returns `(ip_or_hostname, tcp_port)` of the server this connection is connected to.
+    ///
+    /// Useful for multi-host `Opts` (see [`crate::OptsBuilder::additional_hosts`]).
+    /// Returns `None` for socket connections.
+    pub fn connected_host(&self) -> Option<(String, u16)> {
+        self.0
+            .connected_host
+            .as_ref()
+            .map(|(host, port)| (host.to_string(), *port))
+    }
+
     /// Returns number of rows affected by the last query.
     pub fn affected_rows(&self) -> u64 {
         self.0
@@ -282,6 +344,20 @@ impl Conn {
             .map(Option::unwrap_or_default)
     }
 
+    /// Session state accumulated from every session state change reported by the server
+    /// since the connection was established.
+    ///
+    /// Only values tracked by the server are reported (see
+    /// [`crate::OptsBuilder::track_session_state`]).
+    pub fn session_state(&self) -> &SessionState {
+        &self.0.session_state
+    }
+
+    /// Returns the time since the underlying stream was established.
+    pub(crate) fn connection_age(&self) -> std::time::Duration {
+        self.0.connected_at.elapsed()
+    }
+
     fn stream_ref(&self) -> &MySyncFramed<Stream> {
         self.0.stream.as_ref().expect("incomplete connection")
     }
@@ -301,7 +377,13 @@ impl Conn {
     /// Check the connection can be improved.
     #[allow(unused_assignments)]
     fn can_improved(&mut self) -> Result<Option<Opts>> {
-        if self.0.opts.get_prefer_socket() && self.0.opts.addr_is_loopback() {
+        let is_loopback = self
+            .0
+            .connected_host
+            .as_ref()
+            .map(|(host, _)| host_is_loopback(host))
+            .unwrap_or(false);
+        if self.0.opts.get_prefer_socket() && is_loopback {
             let mut socket = None;
             #[cfg(test)]
             {
@@ -346,18 +428,42 @@ impl Conn {
                 conn
             }
         };
-        for cmd in conn.0.opts.get_init() {
-            conn.query_drop(cmd)?;
-        }
+        conn.enable_session_tracking()?;
+        conn.run_init()?;
         Ok(conn)
     }
 
+    /// Enables session state trackers supported by the server
+    /// if `Opts::get_track_session_state` is `true`.
+    fn enable_session_tracking(&mut self) -> Result<()> {
+        if self.0.opts.get_track_session_state() {
+            let query =
+                track_session_state_query(self.0.server_version, self.0.mariadb_server_version);
+            if let Some(query) = query {
+                self.query_drop(query)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Executes commands given by `Opts::get_init` and the `after_connect` hook.
+    pub(crate) fn run_init(&mut self) -> Result<()> {
+        for cmd in self.0.opts.get_init() {
+            self.query_drop(cmd)?;
+        }
+        if let Some(hooks) = self.0.opts.get_hooks().cloned() {
+            hooks.after_connect(self)?;
+        }
+        Ok(())
+    }
+
     fn soft_reset(&mut self) -> Result<()> {
         self.write_command(Command::COM_RESET_CONNECTION, &[])?;
         let packet = self.read_packet()?;
         self.handle_ok::<CommonOkPacket>(&packet)?;
         self.0.last_command = 0;
         self.0.stmt_cache.clear();
+        self.0.session_state.reset();
         Ok(())
     }
 
@@ -368,6 +474,7 @@ impl Conn {
         self.0.connection_id = 0;
         self.0.character_set = 0;
         self.0.ok_packet = None;
+        self.0.session_state = SessionState::default();
         self.0.last_command = 0;
         self.0.connected = false;
         self.0.has_results = false;
@@ -376,6 +483,8 @@ impl Conn {
     }
 
     /// Resets `MyConn` (drops state then reconnects).
+    ///
+    /// Session state trackers are enabled again (see `OptsBuilder::track_session_state`).
     pub fn reset(&mut self) -> Result<()> {
         match (self.0.server_version, self.0.mariadb_server_version) {
             (Some(ref version), _) if *version > (5, 7, 3) => {
@@ -385,19 +494,25 @@ impl Conn {
                 self.soft_reset().or_else(|_| self.hard_reset())
             }
             _ => self.hard_reset(),
-        }
+        }?;
+        self.enable_session_tracking()
     }
 
     fn switch_to_ssl(&mut self, ssl_opts: SslOpts) -> Result<()> {
         let stream = self.0.stream.take().expect("incomplete conn");
         let (in_buf, out_buf, codec, stream) = stream.destruct();
-        let stream = stream.make_secure(self.0.opts.get_host(), ssl_opts)?;
+        let host = match self.0.connected_host {
+            Some((ref host, _)) => host.clone(),
+            None => self.0.opts.get_host(),
+        };
+        let stream = stream.make_secure(host, ssl_opts)?;
         let stream = MySyncFramed::construct(in_buf, out_buf, codec, stream);
         self.0.stream = Some(stream);
         Ok(())
     }
 
     fn connect_stream(&mut self) -> Result<()> {
+        self.0.connected_at = Instant::now();
         let opts = &self.0.opts;
         let read_timeout = opts.get_read_timeout().cloned();
         let write_timeout = opts.get_write_timeout().cloned();
@@ -411,57 +526,51 @@ impl Conn {
         let tcp_nodelay = opts.get_tcp_nodelay();
         let tcp_connect_timeout = opts.get_tcp_connect_timeout();
         let bind_address = opts.bind_address().cloned();
+
         #[cfg(not(target_os = "wasi"))]
-        {
-            let stream = if let Some(socket) = opts.get_socket() {
-                Stream::connect_socket(&*socket, read_timeout, write_timeout)?
-            } else {
-                let port = opts.get_tcp_port();
-                let ip_or_hostname = match opts.get_host() {
-                    url::Host::Domain(domain) => domain,
-                    url::Host::Ipv4(ip) => ip.to_string(),
-                    url::Host::Ipv6(ip) => ip.to_string(),
-                };
-                Stream::connect_tcp(
-                    &*ip_or_hostname,
-                    port,
-                    read_timeout,
-                    write_timeout,
-                    tcp_keepalive_time,
-                    #[cfg(any(target_os = "linux", target_os = "macos",))]
-                    tcp_keepalive_probe_interval_secs,
-                    #[cfg(any(target_os = "linux", target_os = "macos",))]
-                    tcp_keepalive_probe_count,
-                    #[cfg(target_os = "linux")]
-                    tcp_user_timeout,
-                    tcp_nodelay,
-                    tcp_connect_timeout,
-                    bind_address,
-                )?
-            };
+        if let Some(socket) = opts.get_socket() {
+            let stream = Stream::connect_socket(&*socket, read_timeout, write_timeout)?;
             self.0.stream = Some(MySyncFramed::new(stream));
+            self.0.connected_host = None;
+            return Ok(());
         }
-        #[cfg(target_os = "wasi")]
-        {
-            let port = opts.get_tcp_port();
-            let ip_or_hostname = match opts.get_host() {
-                url::Host::Domain(domain) => domain,
+
+        // Unreachable hosts are skipped, other errors are returned as is.
+        let mut last_err = None;
+        for (host, port) in opts.hosts_to_try() {
+            let ip_or_hostname = match host {
+                url::Host::Domain(ref domain) => domain.clone(),
                 url::Host::Ipv4(ip) => ip.to_string(),
                 url::Host::Ipv6(ip) => ip.to_string(),
             };
-            let stream = Stream::connect_tcp(
+            let result = Stream::connect_tcp(
                 &*ip_or_hostname,
                 port,
                 read_timeout,
                 write_timeout,
                 tcp_keepalive_time,
+                #[cfg(any(target_os = "linux", target_os = "macos",))]
+                tcp_keepalive_probe_interval_secs,
+                #[cfg(any(target_os = "linux", target_os = "macos",))]
+                tcp_keepalive_probe_count,
+                #[cfg(target_os = "linux")]
+                tcp_user_timeout,
                 tcp_nodelay,
                 tcp_connect_timeout,
                 bind_address,
-            )?;
-            self.0.stream = Some(MySyncFramed::new(stream));
+            );
+            match result {
+                Ok(stream) => {
+                    self.0.stream = Some(MySyncFramed::new(stream));
+                    self.0.connected_host = Some((host, port));
+                    return Ok(());
+                }
+                Err(err @ DriverError(CouldNotConnect(_)))
+                | Err(err @ DriverError(ConnectTimeout)) => last_err = Some(err),
+                Err(err) => return Err(err),
+            }
         }
-        Ok(())
+        Err(last_err.expect("there is at least one host to try"))
     }
 
     fn raw_read_packet(&mut self, buffer: &mut Vec<u8>) -> Result<()> {
@@ -482,8 +591,8 @@ impl Conn {
                             self.handle_err();
                             return Err(MySqlError(From::from(server_error)));
                         }
-                        ErrPacket::Progress(_progress_report) => {
-                            // TODO: Report progress
+                        ErrPacket::Progress(progress_report) => {
+                            self.report_progress(&progress_report)?;
                             continue;
                         }
                     }
@@ -497,6 +606,19 @@ impl Conn {
         }
     }
 
+    fn report_progress(&mut self, progress_report: &ProgressReport<'_>) -> Result<()> {
+        let maybe_handler = self
+            .0
+            .progress_handler
+            .clone()
+            .or_else(|| self.0.opts.get_progress_handler().cloned());
+        if let Some(handler) = maybe_handler {
+            let handler_fn = &mut *handler.0.lock()?;
+            handler_fn(progress_report);
+        }
+        Ok(())
+    }
+
     fn drop_packet(&mut self) -> Result<()> {
         self.read_packet().map(drop)
     }
@@ -514,11 +636,28 @@ impl Conn {
 
     fn handle_handshake(&mut self, hp: &HandshakePacket<'_>) {
         self.0.capability_flags = hp.capabilities() & self.get_client_flags();
+        if self
+            .0
+            .capability_flags
+            .contains(CapabilityFlags::CLIENT_ZSTD_COMPRESSION_ALGORITHM)
+        {
+            // server chooses zlib if both algorithms are requested
+            self.0
+                .capability_flags
+                .remove(CapabilityFlags::CLIENT_COMPRESS);
+        }
         self.0.status_flags = hp.status_flags();
         self.0.connection_id = hp.connection_id();
         self.0.character_set = hp.default_collation();
         self.0.server_version = hp.server_version_parsed();
         self.0.mariadb_server_version = hp.maria_db_server_version_parsed();
+        self.0.session_state = SessionState::new(
+            self.0
+                .opts
+                .get_db_name()
+                .filter(|db_name| !db_name.is_empty())
+                .map(Into::into),
+        );
     }
 
     fn handle_ok<'a, T: OkPacketKind>(
@@ -529,6 +668,14 @@ impl Conn {
             .parse::<OkPacketDeserializer<T>>(self.0.capability_flags)?
             .into_inner();
         self.0.status_flags = ok.status_flags();
+        if ok
+            .status_flags()
+            .contains(StatusFlags::SERVER_SESSION_STATE_CHANGED)
+        {
+            if let Some(data) = ok.session_state_info_ref() {
+                self.0.session_state.update(data);
+            }
+        }
         self.0.ok_packet = Some(ok.clone().into_owned());
         Ok(ok)
     }
@@ -556,16 +703,32 @@ impl Conn {
         }
 
         let nonce = auth_switch_request.plugin_data();
-        let plugin_data = auth_switch_request
-            .auth_plugin()
-            .gen_data(self.0.opts.get_pass(), nonce)
-            .map(Either::Left)
-            .unwrap_or_else(|| Either::Right([]));
-        self.write_struct(&plugin_data)?;
+        if let AuthPlugin::Other(ref name) = auth_switch_request.auth_plugin() {
+            let plugin_data = self.custom_auth_initial_response(name, nonce)?;
+            self.write_packet(&mut plugin_data.as_slice())?;
+        } else {
+            let plugin_data = auth_switch_request
+                .auth_plugin()
+                .gen_data(self.0.opts.get_pass(), nonce)
+                .map(Either::Left)
+                .unwrap_or_else(|| Either::Right([]));
+            self.write_struct(&plugin_data)?;
+        }
         self.continue_auth(&auth_switch_request.auth_plugin(), nonce, true)
     }
 
+    /// Replaces user and password with the ones given by the credentials provider, if any.
+    fn refresh_credentials(&mut self) -> Result<()> {
+        if let Some(provider) = self.0.opts.get_credentials_provider().cloned() {
+            let credentials = provider.get_credentials()?;
+            self.0.opts.set_credentials(credentials);
+        }
+        Ok(())
+    }
+
     fn do_handshake(&mut self) -> Result<()> {
+        self.refresh_credentials()?;
+
         let payload = self.read_packet()?;
         let handshake = ParseBuf(&*payload).parse::<HandshakePacket>(())?;
 
@@ -611,13 +774,14 @@ impl Conn {
         let auth_plugin = handshake
             .auth_plugin()
             .unwrap_or(AuthPlugin::MysqlNativePassword);
+
         if let AuthPlugin::Other(ref name) = auth_plugin {
-            let plugin_name = String::from_utf8_lossy(name).into();
-            return Err(DriverError(UnknownAuthPlugin(plugin_name)));
+            let auth_data = self.custom_auth_initial_response(name, &*nonce)?;
+            self.write_handshake_response(&auth_plugin, Some(&*auth_data))?;
+        } else {
+            let auth_data = auth_plugin.gen_data(self.0.opts.get_pass(), &*nonce);
+            self.write_handshake_response(&auth_plugin, auth_data.as_deref())?;
         }
-
-        let auth_data = auth_plugin.gen_data(self.0.opts.get_pass(), &*nonce);
-        self.write_handshake_response(&auth_plugin, auth_data.as_deref())?;
         self.continue_auth(&auth_plugin, &*nonce, false)?;
 
         if self
@@ -628,6 +792,15 @@ impl Conn {
             self.switch_to_compressed();
         }
 
+        #[cfg(feature = "zstd")]
+        if self
+            .0
+            .capability_flags
+            .contains(CapabilityFlags::CLIENT_ZSTD_COMPRESSION_ALGORITHM)
+        {
+            self.switch_to_zstd_compressed();
+        }
+
         Ok(())
     }
 
@@ -637,6 +810,18 @@ impl Conn {
             .compress(Compression::default());
     }
 
+    #[cfg(feature = "zstd")]
+    fn switch_to_zstd_compressed(&mut self) {
+        let level = self.0.opts.get_zstd_compress().unwrap_or_default();
+        let stream = self.0.stream.take().expect("incomplete conn");
+        let (in_buf, out_buf, mut codec, stream) = stream.destruct();
+        // Codec is responsible for the framing, while the stream is responsible
+        // for the compression itself.
+        codec.compress(Compression::none());
+        let stream = Stream::Zstd(Box::new(crate::io::ZstdStream::new(stream, level.into())));
+        self.0.stream = Some(MySyncFramed::construct(in_buf, out_buf, codec, stream));
+    }
+
     fn get_client_flags(&self) -> CapabilityFlags {
         let mut client_flags = CapabilityFlags::CLIENT_PROTOCOL_41
             | CapabilityFlags::CLIENT_SECURE_CONNECTION
@@ -648,10 +833,19 @@ impl Conn {
             | CapabilityFlags::CLIENT_PS_MULTI_RESULTS
             | CapabilityFlags::CLIENT_PLUGIN_AUTH
             | CapabilityFlags::CLIENT_CONNECT_ATTRS
+            | CapabilityFlags::CLIENT_PROGRESS_OBSOLETE
+            | CapabilityFlags::CLIENT_QUERY_ATTRIBUTES
+            | CapabilityFlags::CLIENT_SESSION_TRACK
             | (self.0.capability_flags & CapabilityFlags::CLIENT_LONG_FLAG);
         if self.0.opts.get_compress().is_some() {
             client_flags.insert(CapabilityFlags::CLIENT_COMPRESS);
         }
+        if self.0.opts.get_zstd_compress().is_some() {
+            // zlib is requested as a fallback
+            client_flags.insert(CapabilityFlags::CLIENT_COMPRESS);
+            #[cfg(feature = "zstd")]
+            client_flags.insert(CapabilityFlags::CLIENT_ZSTD_COMPRESSION_ALGORITHM);
+        }
         if let Some(db_name) = self.0.opts.get_db_name() {
             if !db_name.is_empty() {
                 client_flags.insert(CapabilityFlags::CLIENT_CONNECT_WITH_DB);
@@ -724,6 +918,14 @@ impl Conn {
 
         let mut buf = get_buffer();
         handshake_response.serialize(buf.as_mut());
+        if self
+            .0
+            .capability_flags
+            .contains(CapabilityFlags::CLIENT_ZSTD_COMPRESSION_ALGORITHM)
+        {
+            let level = self.0.opts.get_zstd_compress().unwrap_or_default();
+            buf.as_mut().put_u8(level);
+        }
         self.write_packet(&mut &*buf)
     }
 
@@ -743,8 +945,69 @@ impl Conn {
                 Ok(())
             }
             AuthPlugin::Other(ref name) => {
-                let plugin_name = String::from_utf8_lossy(name).into();
-                Err(DriverError(UnknownAuthPlugin(plugin_name)))
+                self.continue_custom_auth(name, nonce, auth_switched)?;
+                Ok(())
+            }
+        }
+    }
+
+    fn auth_plugin_handler(&self, name: &[u8]) -> Result<Arc<dyn AuthPluginHandler>> {
+        let plugin_name = String::from_utf8_lossy(name);
+        match self.0.opts.get_auth_plugin_handler(&plugin_name) {
+            Some(handler) => Ok(handler.clone()),
+            None => builtin_auth_plugin_handler(&plugin_name)
+                .ok_or_else(|| DriverError(UnknownAuthPlugin(plugin_name.into()))),
+        }
+    }
+
+    fn custom_auth_initial_response(&self, name: &[u8], nonce: &[u8]) -> Result<Vec<u8>> {
+        let handler = self.auth_plugin_handler(name)?;
+        let server_public_key = self.auth_plugin_public_key(name)?;
+        let ctx = AuthPluginContext::new(
+            self.0.opts.get_user(),
+            self.0.opts.get_pass(),
+            nonce,
+            !self.is_insecure() || self.is_socket(),
+        )
+        .with_server_public_key(server_public_key.as_deref());
+        handler.initial_response(&ctx)
+    }
+
+    fn continue_custom_auth(
+        &mut self,
+        name: &[u8],
+        nonce: &[u8],
+        auth_switched: bool,
+    ) -> Result<()> {
+        let handler = self.auth_plugin_handler(name)?;
+        let opts = self.0.opts.clone();
+        let server_public_key = self.auth_plugin_public_key(name)?;
+        let ctx = AuthPluginContext::new(
+            opts.get_user(),
+            opts.get_pass(),
+            nonce,
+            !self.is_insecure() || self.is_socket(),
+        )
+        .with_server_public_key(server_public_key.as_deref());
+
+        loop {
+            let payload = self.read_packet()?;
+
+            match payload[0] {
+                // auth ok
+                0x00 => return self.handle_ok::<CommonOkPacket>(&payload).map(drop),
+                // more data
+                0x01 => {
+                    if let Some(response) = handler.handle_more_data(&ctx, &payload[1..])? {
+                        self.write_packet(&mut response.as_slice())?;
+                    }
+                }
+                // auth switch
+                0xfe if !auth_switched => {
+                    let auth_switch_request = ParseBuf(&*payload).parse(())?;
+                    return self.perform_auth_switch(auth_switch_request);
+                }
+                _ => return Err(DriverError(UnexpectedPacket)),
             }
         }
     }
@@ -802,9 +1065,18 @@ impl Conn {
                         pass.push(0);
                         self.write_packet(&mut pass.as_slice())?;
                     } else {
+                        // Pinned key is only compared with the server key,
+                        // the password is always encrypted with the pinned key.
+                        let pinned_key = self.server_public_key()?;
                         self.write_packet(&mut &[0x02][..])?;
                         let payload = self.read_packet()?;
-                        let key = &payload[1..];
+                        let key = match pinned_key {
+                            Some(key) if !is_same_public_key(&key, &payload[1..]) => {
+                                return Err(DriverError(ServerPublicKeyMismatch));
+                            }
+                            Some(key) => key,
+                            None => payload[1..].to_vec(),
+                        };
                         let mut pass = self
                             .0
                             .opts
@@ -815,7 +1087,7 @@ impl Conn {
                         for i in 0..pass.len() {
                             pass[i] ^= nonce[i % nonce.len()];
                         }
-                        let encrypted_pass = crypto::encrypt(&*pass, key);
+                        let encrypted_pass = crypto::encrypt(&*pass, &*key);
                         self.write_packet(&mut encrypted_pass.as_slice())?;
                     }
 
@@ -832,6 +1104,32 @@ impl Conn {
         }
     }
 
+    /// Loads the server RSA public key for the given auth plugin, if the plugin needs it.
+    fn auth_plugin_public_key(&self, name: &[u8]) -> Result<Option<Vec<u8>>> {
+        // only `sha256_password` uses the key and only over an insecure connection
+        if name == b"sha256_password" && self.is_insecure() && !self.is_socket() {
+            self.server_public_key()
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Loads the server RSA public key defined in `Opts`, if any.
+    fn server_public_key(&self) -> Result<Option<Vec<u8>>> {
+        let path = match self.0.opts.get_server_public_key_path() {
+            Some(path) => path,
+            None => return Ok(None),
+        };
+
+        let key = std::fs::read(path)?;
+        match pem::parse(&*key) {
+            Ok(pem) if pem.tag == "PUBLIC KEY" || pem.tag == "RSA PUBLIC KEY" => Ok(Some(key)),
+            _ => Err(DriverError(InvalidServerPublicKey(
+                path.display().to_string(),
+            ))),
+        }
+    }
+
     fn reset_seq_id(&mut self) {
         self.stream_mut().codec_mut().reset_seq_id();
     }
@@ -883,14 +1181,22 @@ impl Conn {
         stmt: &Statement,
         params: Params,
     ) -> Result<Or<Vec<Column>, OkPacket<'static>>> {
-        let exec_request = match &params {
+        self._execute_with_cursor(stmt, params, CursorType::CURSOR_TYPE_NO_CURSOR)
+    }
+
+    fn _execute_with_cursor(
+        &mut self,
+        stmt: &Statement,
+        params: Params,
+        cursor_type: CursorType,
+    ) -> Result<Or<Vec<Column>, OkPacket<'static>>> {
+        let (exec_request, as_long_data) = match &params {
             Params::Empty => {
                 if stmt.num_params() != 0 {
                     return Err(DriverError(MismatchedStmtParams(stmt.num_params(), 0)));
                 }
 
-                let (body, _) = ComStmtExecuteRequestBuilder::new(stmt.id()).build(&[]);
-                body
+                ComStmtExecuteRequestBuilder::new(stmt.id()).build(&[])
             }
             Params::Positional(params) => {
                 if stmt.num_params() != params.len() as u16 {
@@ -900,27 +1206,110 @@ impl Conn {
                     )));
                 }
 
-                let (body, as_long_data) =
-                    ComStmtExecuteRequestBuilder::new(stmt.id()).build(&*params);
-
-                if as_long_data {
-                    self.send_long_data(stmt.id(), &*params)?;
-                }
-
-                body
+                ComStmtExecuteRequestBuilder::new(stmt.id()).build(&*params)
             }
             Params::Named(_) => {
                 if let Some(named_params) = stmt.named_params.as_ref() {
-                    return self._execute(stmt, params.into_positional(named_params)?);
+                    return self._execute_with_cursor(
+                        stmt,
+                        params.into_positional(named_params)?,
+                        cursor_type,
+                    );
                 } else {
                     return Err(DriverError(NamedParamsForPositionalQuery));
                 }
             }
         };
-        self.write_command_raw(&exec_request)?;
+        let values: &[Value] = match &params {
+            Params::Positional(params) => params,
+            _ => &[],
+        };
+
+        if as_long_data {
+            self.send_long_data(stmt.id(), values)?;
+        }
+
+        let mut buf = get_buffer();
+        if self
+            .0
+            .capability_flags
+            .contains(CapabilityFlags::CLIENT_QUERY_ATTRIBUTES)
+        {
+            ComStmtExecuteWithAttributes {
+                stmt_id: stmt.id(),
+                cursor_type,
+                params: values,
+                attributes: &self.0.query_attributes,
+                as_long_data,
+            }
+            .serialize(buf.as_mut());
+        } else {
+            exec_request.serialize(buf.as_mut());
+            // `ComStmtExecuteRequestBuilder` always requests no cursor, so the flags byte,
+            // that follows the command byte and the statement id, is patched here
+            buf.as_mut()[5] = cursor_type.bits();
+        }
+        self.reset_seq_id();
+        self.0.last_command = buf[0];
+        self.write_packet(&mut &*buf)?;
         self.handle_result_set()
     }
 
+    /// Requests the next `num_rows` rows of an open cursor (see [`Cursor`]).
+    fn fetch_rows(&mut self, stmt_id: u32, num_rows: u32) -> Result<()> {
+        let mut data = [0_u8; 8];
+        data[..4].copy_from_slice(&stmt_id.to_le_bytes());
+        data[4..].copy_from_slice(&num_rows.to_le_bytes());
+        self.write_command(Command::COM_STMT_FETCH, &data)?;
+        self.0.has_results = true;
+        Ok(())
+    }
+
+    /// Resets the statement, i.e. closes its cursor (if any).
+    fn reset_stmt(&mut self, stmt_id: u32) -> Result<()> {
+        self.write_command(Command::COM_STMT_RESET, &stmt_id.to_le_bytes())?;
+        self.drop_packet()
+    }
+
+    /// Executes the statement with a read-only server-side cursor, so that rows are fetched
+    /// from the server in batches of `fetch_size` rows (`COM_STMT_FETCH`).
+    ///
+    /// Use it to iterate over huge result sets, that shouldn't be streamed
+    /// all at once. Note that the connection is busy until the cursor is dropped.
+    ///
+    /// Server may refuse to open a cursor (e.g. for a `CALL` statement), in this case rows
+    /// of the first result set are streamed as usual and other result sets are discarded.
+    ///
+    /// ```rust
+    /// # mysql::doctest_wrapper!(__result, {
+    /// # use mysql::*;
+    /// # use mysql::prelude::*;
+    /// let mut conn = Conn::new(get_opts())?;
+    /// let stmt = conn.prep("SELECT 1 UNION ALL SELECT ? UNION ALL SELECT 3")?;
+    ///
+    /// let cursor = conn.exec_cursor(&stmt, (2,), 2)?;
+    /// assert_eq!(cursor.columns().len(), 1);
+    /// let values = cursor
+    ///     .map(|row| row.map(from_row::<u8>))
+    ///     .collect::<Result<Vec<_>>>()?;
+    /// assert_eq!(values, vec![1, 2, 3]);
+    /// # });
+    /// ```
+    pub fn exec_cursor<S, P>(&mut self, stmt: S, params: P, fetch_size: u32) -> Result<Cursor<'_>>
+    where
+        S: AsStatement,
+        P: Into<Params>,
+    {
+        let statement = stmt.as_statement(self)?;
+        let meta = self._execute_with_cursor(
+            &*statement,
+            params.into(),
+            CursorType::CURSOR_TYPE_READ_ONLY,
+        )?;
+        let stmt_id = statement.id();
+        Ok(Cursor::new(self, stmt_id, meta, fetch_size))
+    }
+
     fn _start_transaction(&mut self, tx_opts: TxOpts) -> Result<()> {
         if let Some(i_level) = tx_opts.isolation_level() {
             self.query_drop(format!("SET TRANSACTION ISOLATION LEVEL {}", i_level))?;
@@ -1000,8 +1389,13 @@ impl Conn {
                     let column = ParseBuf(&*pld).parse(())?;
                     columns.push(column);
                 }
-                // skip eof packet
-                self.drop_packet()?;
+                // eof packet carries status flags (e.g. `SERVER_STATUS_CURSOR_EXISTS`)
+                let eof = self.read_packet()?;
+                if let Ok(eof) = ParseBuf(&*eof)
+                    .parse::<OkPacketDeserializer<ResultSetTerminator>>(self.0.capability_flags)
+                {
+                    self.0.status_flags = eof.into_inner().status_flags();
+                }
                 self.0.has_results = column_count > 0;
                 Ok(Or::A(columns))
             }
@@ -1009,10 +1403,51 @@ impl Conn {
     }
 
     fn _query(&mut self, query: &str) -> Result<Or<Vec<Column>, OkPacket<'static>>> {
-        self.write_command(Command::COM_QUERY, query.as_bytes())?;
+        if self
+            .0
+            .capability_flags
+            .contains(CapabilityFlags::CLIENT_QUERY_ATTRIBUTES)
+        {
+            let mut data = get_buffer();
+            put_query_attributes(data.as_mut(), &self.0.query_attributes);
+            data.as_mut().extend_from_slice(query.as_bytes());
+            self.write_command(Command::COM_QUERY, &data)?;
+        } else {
+            self.write_command(Command::COM_QUERY, query.as_bytes())?;
+        }
         self.handle_result_set()
     }
 
+    /// Returns a wrapper, that sends the given query attributes with every query and
+    /// statement execution (requires MySql 8.0.23 or newer).
+    ///
+    /// Query attributes are metadata, that is available to server-side plugins and components
+    /// (e.g. audit or tracing), as well as to queries via the `mysql_query_attribute_string`
+    /// function of the `query_attributes` component. Attributes are silently ignored if the
+    /// server does not support them.
+    ///
+    /// ```rust
+    /// # mysql::doctest_wrapper!(__result, {
+    /// # use mysql::*;
+    /// # use mysql::prelude::*;
+    /// let mut conn = Conn::new(get_opts())?;
+    ///
+    /// conn.with_query_attributes(&[("traceparent", "00-0af7651916cd43dd-b7ad6b7169203331-01")])
+    ///     .query_drop("DO 1")?;
+    /// # });
+    /// ```
+    pub fn with_query_attributes<N, V>(&mut self, attributes: &[(N, V)]) -> WithQueryAttributes<'_>
+    where
+        N: AsRef<str>,
+        V: Into<Value> + Clone,
+    {
+        self.0.query_attributes = attributes
+            .iter()
+            .map(|(name, value)| (name.as_ref().to_owned(), value.clone().into()))
+            .collect();
+        WithQueryAttributes::new(self)
+    }
+
     /// Executes [`COM_PING`](http://dev.mysql.com/doc/internals/en/com-ping.html)
     /// on `Conn`. Return `true` on success or `false` on error.
     pub fn ping(&mut self) -> bool {
@@ -1022,6 +1457,143 @@ impl Conn {
         }
     }
 
+    /// Runs `f` with a query execution deadline.
+    ///
+    /// If `f` is still running at the `deadline`, then the current query is killed
+    /// via `KILL QUERY <connection_id>` issued over a side connection to the same server.
+    /// In this case the interrupted result is drained (and discarded) and
+    /// `DriverError::QueryTimeout` is returned, so that the connection remains usable.
+    /// The error is returned even if the interrupted query has returned early
+    /// without an error (e.g. `SLEEP` returns `1`).
+    ///
+    /// ```rust
+    /// # mysql::doctest_wrapper!(__result, {
+    /// # use mysql::*;
+    /// # use mysql::prelude::*;
+    /// # use std::time::{Duration, Instant};
+    /// let mut conn = Conn::new(get_opts())?;
+    ///
+    /// let deadline = Instant::now() + Duration::from_millis(500);
+    /// match conn.with_deadline(deadline, |conn| conn.query_drop("DO SLEEP(10)")) {
+    ///     Err(Error::DriverError(DriverError::QueryTimeout)) => (),
+    ///     _ => panic!("QueryTimeout error expected"),
+    /// }
+    ///
+    /// // the connection is still usable
+    /// assert_eq!(conn.query_first::<u8, _>("SELECT 1")?, Some(1));
+    /// # });
+    /// ```
+    ///
+    /// **Note:** Not available on WASI, because it requires threads.
+    #[cfg(not(target_os = "wasi"))]
+    pub fn with_deadline<T, F>(&mut self, deadline: Instant, f: F) -> Result<T>
+    where
+        F: FnOnce(&mut Conn) -> Result<T>,
+    {
+        let (done, done_rx) = sync_channel::<()>(0);
+        let finished = Arc::new(AtomicBool::new(false));
+        let killed = Arc::new(AtomicBool::new(false));
+        let watchdog = {
+            let handle = self.cancel_handle();
+            let finished = finished.clone();
+            let killed = killed.clone();
+            thread::Builder::new()
+                .name("mysql-query-deadline".into())
+                .spawn(move || {
+                    let timeout = deadline.saturating_duration_since(Instant::now());
+                    if let Err(RecvTimeoutError::Timeout) = done_rx.recv_timeout(timeout) {
+                        if let Ok(true) = handle.kill_query_unless(&finished) {
+                            killed.store(true, Ordering::SeqCst);
+                        }
+                    }
+                })?
+        };
+
+        let result = f(self);
+
+        // the watchdog must not outlive `f`, otherwise it might kill an unrelated query
+        finished.store(true, Ordering::SeqCst);
+        drop(done);
+        let _ = watchdog.join();
+
+        if killed.load(Ordering::SeqCst) {
+            return Err(DriverError(crate::DriverError::QueryTimeout));
+        }
+        result
+    }
+
+    /// Waits until the server applies the given GTID set (e.g. one given by
+    /// [`SessionState::last_gtids`] of a primary connection).
+    ///
+    /// It is useful to read your own writes from a replica. `timeout` of `None` means
+    /// no timeout.
+    ///
+    /// Uses `WAIT_FOR_EXECUTED_GTID_SET` on MySql and `MASTER_GTID_WAIT` on MariaDB
+    /// (note that MariaDB uses its own GTID format).
+    ///
+    /// MySql prior to 8.0 doesn't honor fractional timeouts, so on MySql `timeout` is
+    /// rounded up to whole seconds, and zero `timeout` (that would mean "wait forever"
+    /// to `WAIT_FOR_EXECUTED_GTID_SET`) only checks whether the set is already applied.
+    /// MariaDB honors the `timeout` as is.
+    pub fn wait_for_gtid_set<T: AsRef<str>>(
+        &mut self,
+        gtid_set: T,
+        timeout: Option<Duration>,
+    ) -> Result<GtidWaitResult> {
+        let gtid_set = gtid_set.as_ref();
+        let is_mariadb = self.0.mariadb_server_version.is_some();
+        let (function, timed_out) = if is_mariadb {
+            ("MASTER_GTID_WAIT", -1)
+        } else {
+            ("WAIT_FOR_EXECUTED_GTID_SET", 1)
+        };
+        let timeout = match timeout {
+            Some(timeout) if !is_mariadb && timeout == Duration::from_secs(0) => {
+                let applied: Option<Option<bool>> =
+                    self.exec_first("SELECT GTID_SUBSET(?, @@GLOBAL.gtid_executed)", (gtid_set,))?;
+                return match applied.flatten() {
+                    Some(true) => Ok(GtidWaitResult::Reached),
+                    Some(false) => Ok(GtidWaitResult::TimedOut),
+                    None => Err(DriverError(UnexpectedPacket)),
+                };
+            }
+            Some(timeout) if !is_mariadb => Some(timeout.as_secs_f64().ceil()),
+            timeout => timeout.map(|timeout| timeout.as_secs_f64()),
+        };
+        let result: Option<Option<i64>> = match timeout {
+            Some(timeout) => {
+                self.exec_first(format!("SELECT {}(?, ?)", function), (gtid_set, timeout))?
+            }
+            None => self.exec_first(format!("SELECT {}(?)", function), (gtid_set,))?,
+        };
+        match result.flatten() {
+            Some(0) => Ok(GtidWaitResult::Reached),
+            Some(x) if x == timed_out => Ok(GtidWaitResult::TimedOut),
+            _ => Err(DriverError(UnexpectedPacket)),
+        }
+    }
+
+    /// Returns a handle, that could be used to cancel work of this connection
+    /// from another thread (see [`CancelHandle`]).
+    pub fn cancel_handle(&self) -> CancelHandle {
+        CancelHandle::new(self.side_opts(), self.connection_id())
+    }
+
+    /// Returns options of a side connection to the same server.
+    fn side_opts(&self) -> Opts {
+        let mut builder =
+            OptsBuilder::from_opts(self.0.opts.clone())
+                .additional_hosts(Vec::<(String, u16)>::new());
+        if let Some((ref host, port)) = self.0.connected_host {
+            builder = builder
+                .ip_or_hostname(Some(host.to_string()))
+                .tcp_port(port);
+        }
+        let mut opts: Opts = builder.into();
+        opts.clear_session_setup();
+        opts
+    }
+
     /// Executes [`COM_INIT_DB`](https://dev.mysql.com/doc/internals/en/com-init-db.html)
     /// on `Conn`.
     pub fn select_db(&mut self, schema: &str) -> bool {
@@ -1038,6 +1610,85 @@ impl Conn {
         Ok(Transaction::new(self.into()))
     }
 
+    /// Runs `f` within a transaction.
+    ///
+    /// Transaction is committed if `f` returns `Ok` and rolled back otherwise. The whole
+    /// transaction is retried with backoff if it fails with an error, that is retryable
+    /// according to the `retry_policy` (e.g. a deadlock), so `f` may be called multiple times.
+    ///
+    /// A transaction, that fails with a connectivity error before commit, is retried as well
+    /// after the connection is reset (see [`Conn::reset`]), so the session state is lost
+    /// (init commands are executed again). An error on commit itself is never retried
+    /// unless it is retryable according to the `retry_policy`, because it is unknown whether
+    /// the transaction was committed.
+    ///
+    /// ```rust
+    /// # mysql::doctest_wrapper!(__result, {
+    /// # use mysql::*;
+    /// # use mysql::prelude::*;
+    /// # let mut conn = Conn::new(get_opts())?;
+    /// conn.query_drop("CREATE TEMPORARY TABLE mysql.tbl(a INT)")?;
+    /// let count = conn.transaction(TxOpts::default(), RetryPolicy::default(), |tx| {
+    ///     tx.query_drop("INSERT INTO mysql.tbl (a) VALUES (1), (2)")?;
+    ///     Ok(tx.affected_rows())
+    /// })?;
+    /// assert_eq!(count, 2);
+    /// # });
+    /// ```
+    pub fn transaction<T, F>(
+        &mut self,
+        tx_opts: TxOpts,
+        retry_policy: RetryPolicy,
+        mut f: F,
+    ) -> Result<T>
+    where
+        F: FnMut(&mut Transaction<'_>) -> Result<T>,
+    {
+        let mut retries = 0;
+        loop {
+            match self.transaction_attempt(tx_opts, &mut f) {
+                Ok(value) => return Ok(value),
+                Err((err, _))
+                    if retries < retry_policy.max_retries() && retry_policy.is_retryable(&err) =>
+                {
+                    thread::sleep(retry_policy.backoff(retries));
+                }
+                Err((err, false))
+                    if retries < retry_policy.max_retries() && err.is_connectivity_error() =>
+                {
+                    self.reset()?;
+                    self.run_init()?;
+                }
+                Err((err, _)) => return Err(err),
+            }
+            retries += 1;
+        }
+    }
+
+    /// Runs a single attempt of [`Conn::transaction`].
+    ///
+    /// Returned error is accompanied by `true` if it happened on commit.
+    pub(crate) fn transaction_attempt<T, F>(
+        &mut self,
+        tx_opts: TxOpts,
+        f: &mut F,
+    ) -> std::result::Result<T, (Error, bool)>
+    where
+        F: FnMut(&mut Transaction<'_>) -> Result<T>,
+    {
+        let mut tx = self
+            .start_transaction(tx_opts)
+            .map_err(|err| (err, false))?;
+        match f(&mut tx) {
+            Ok(value) => tx.commit().map(|()| value).map_err(|err| (err, true)),
+            Err(err) => {
+                // the original error is more important than an error of the rollback
+                let _ = tx.rollback();
+                Err((err, false))
+            }
+        }
+    }
+
     fn _true_prepare(&mut self, query: &[u8]) -> Result<InnerStmt> {
         self.write_command(Command::COM_STMT_PREPARE, query)?;
         let pld = self.read_packet()?;
@@ -1149,6 +1800,14 @@ impl Conn {
         self.0.local_infile_handler = handler;
     }
 
+    /// Sets a callback to handle progress reports of long running commands
+    /// (see [`ProgressHandler`]).
+    /// Specifying `None` will reset the handler to the one specified
+    /// in the `Opts` for this connection.
+    pub fn set_progress_handler(&mut self, handler: Option<ProgressHandler>) {
+        self.0.progress_handler = handler;
+    }
+
     pub fn no_backslash_escape(&self) -> bool {
         self.0
             .status_flags
@@ -1221,8 +1880,22 @@ impl Queryable for Conn {
     }
 }
 
+/// Returns `true` if both PEM-encoded public keys have the same contents.
+fn is_same_public_key(key: &[u8], other: &[u8]) -> bool {
+    match (pem::parse(key), pem::parse(other)) {
+        (Ok(key), Ok(other)) => key.contents == other.contents,
+        _ => key == other,
+    }
+}
+
 impl Drop for Conn {
     fn drop(&mut self) {
+        if self.0.stream.is_some() {
+            if let Some(hooks) = self.0.opts.get_hooks().cloned() {
+                hooks.on_close(self);
+            }
+        }
+
         let stmt_cache = mem::replace(&mut self.0.stmt_cache, StmtCache::new(0));
 
         for (_, entry) in stmt_cache.into_iter() {
@@ -1243,22 +1916,28 @@ mod test {
             collections::HashMap,
             io::Write,
             iter, process,
-            sync::mpsc::{channel, sync_channel},
+            sync::{
+                mpsc::{channel, sync_channel},
+                Arc, Mutex,
+            },
             thread::spawn,
-            time::Duration,
+            time::{Duration, Instant},
         };
 
         use mysql_common::{binlog::events::EventData, packets::binlog_request::BinlogRequest};
         use time::PrimitiveDateTime;
 
         use crate::{
+            conn::session_state::track_session_state_query,
+            consts::CapabilityFlags,
             from_row, from_value, params,
             prelude::*,
             test_misc::get_opts,
             Conn,
-            DriverError::{MissingNamedParameter, NamedParamsForPositionalQuery},
+            DriverError::{MissingNamedParameter, NamedParamsForPositionalQuery, QueryTimeout},
             Error::DriverError,
-            LocalInfileHandler, Opts, OptsBuilder, Pool, TxOpts,
+            GtidWaitResult, LocalInfileHandler, Opts, OptsBuilder, Pool, ProgressHandler,
+            RetryPolicy, ServerError, TxOpts,
             Value::{self, Bytes, Date, Float, Int, NULL},
         };
 
@@ -1601,18 +2280,126 @@ mod test {
         }
 
         #[test]
-        fn should_start_commit_and_rollback_transactions() {
+        fn should_handle_savepoints() {
             let mut conn = Conn::new(get_opts()).unwrap();
-            conn.query_drop(
-                "CREATE TEMPORARY TABLE mysql.tbl(id INT NOT NULL PRIMARY KEY AUTO_INCREMENT, a INT)",
-            )
-            .unwrap();
-            let _ = conn
-                .start_transaction(TxOpts::default())
-                .and_then(|mut t| {
-                    t.query_drop("INSERT INTO mysql.tbl(a) VALUES(1)").unwrap();
-                    assert_eq!(t.last_insert_id(), Some(1));
-                    assert_eq!(t.affected_rows(), 1);
+            conn.query_drop("CREATE TEMPORARY TABLE mysql.tbl(a INT)")
+                .unwrap();
+
+            fn insert<Q: Queryable>(queryable: &mut Q, a: u8) {
+                queryable
+                    .exec_drop("INSERT INTO mysql.tbl (a) VALUES (?)", (a,))
+                    .unwrap();
+            }
+
+            let mut tx = conn.start_transaction(TxOpts::default()).unwrap();
+            insert(&mut tx, 1);
+            {
+                let mut sp = tx.savepoint().unwrap();
+                insert(&mut sp, 2);
+                {
+                    let mut nested = sp.savepoint().unwrap();
+                    insert(&mut nested, 3);
+                    nested.rollback_to().unwrap();
+                }
+                {
+                    let mut nested = sp.savepoint().unwrap();
+                    insert(&mut nested, 4);
+                    // implicit rollback
+                }
+                let mut nested = sp.savepoint().unwrap();
+                insert(&mut nested, 5);
+                nested.release().unwrap();
+                sp.release().unwrap();
+            }
+            {
+                let mut sp = tx.savepoint().unwrap();
+                insert(&mut sp, 6);
+                // implicit rollback
+            }
+            tx.commit().unwrap();
+
+            let values: Vec<u8> = conn.query("SELECT a FROM mysql.tbl ORDER BY a").unwrap();
+            assert_eq!(values, vec![1, 2, 5]);
+        }
+
+        #[test]
+        fn should_retry_transaction_closure() {
+            let mut conn = Conn::new(get_opts()).unwrap();
+            conn.query_drop("CREATE TEMPORARY TABLE mysql.tbl(a INT)")
+                .unwrap();
+
+            let deadlock = || {
+                crate::Error::MySqlError(crate::MySqlError {
+                    state: "40001".into(),
+                    message: "Deadlock found when trying to get lock".into(),
+                    code: ServerError::ER_LOCK_DEADLOCK as u16,
+                })
+            };
+            let policy = RetryPolicy::default().set_initial_backoff(Duration::from_millis(1));
+
+            let mut attempts = 0;
+            let value = conn
+                .transaction(TxOpts::default(), policy, |tx| {
+                    attempts += 1;
+                    tx.query_drop("INSERT INTO mysql.tbl (a) VALUES (1)")?;
+                    if attempts < 3 {
+                        return Err(deadlock());
+                    }
+                    Ok(attempts)
+                })
+                .unwrap();
+            assert_eq!(value, 3);
+            // failed attempts were rolled back
+            let count: Option<u8> = conn.query_first("SELECT COUNT(*) FROM mysql.tbl").unwrap();
+            assert_eq!(count, Some(1));
+
+            let mut attempts = 0;
+            let result = conn.transaction(TxOpts::default(), policy.set_max_retries(1), |_| {
+                attempts += 1;
+                Err::<(), _>(deadlock())
+            });
+            assert!(result.is_err());
+            assert_eq!(attempts, 2);
+
+            // other errors aren't retried
+            let mut attempts = 0;
+            let result = conn.transaction(TxOpts::default(), policy, |tx| {
+                attempts += 1;
+                tx.query_drop("SELECT * FROM mysql.nonexistent")
+            });
+            assert!(result.is_err());
+            assert_eq!(attempts, 1);
+
+            // connection is reset before retrying a connectivity error
+            let mut attempts = 0;
+            let value = conn
+                .transaction(TxOpts::default(), policy, |tx| {
+                    attempts += 1;
+                    if attempts < 2 {
+                        return Err(crate::Error::server_disconnected());
+                    }
+                    tx.query_first::<u8, _>("SELECT 1")
+                })
+                .unwrap();
+            assert_eq!(value, Some(1));
+            assert_eq!(attempts, 2);
+            // temporary table is dropped by the reset
+            assert!(conn.query_drop("SELECT * FROM mysql.tbl").is_err());
+        }
+
+        #[test]
+        fn should_start_commit_and_rollback_transactions() {
+            let mut conn = Conn::new(get_opts()).unwrap();
+            conn.query_drop(
+                "CREATE TEMPORARY TABLE mysql.tbl(id INT NOT NULL PRIMARY KEY AUTO_INCREMENT, a INT)",
+            )
+            .unwrap();
+            let _ = conn
+                .start_transaction(TxOpts::default())
+                .and_then(|mut t| {
+                    t.query_drop("INSERT INTO mysql.tbl(a) VALUES(1)").unwrap();
+                    assert_eq!(t.last_insert_id(), Some(1));
+                    assert_eq!(t.affected_rows(), 1);
                     t.query_drop("INSERT INTO mysql.tbl(a) VALUES(2)").unwrap();
                     t.commit().unwrap();
                     Ok(())
@@ -1719,6 +2506,96 @@ mod test {
             assert_eq!(count, 1536);
         }
 
+        #[test]
+        fn should_report_progress() {
+            let reports = Arc::new(Mutex::new(Vec::new()));
+            let handler = {
+                let reports = reports.clone();
+                ProgressHandler::new(move |report| {
+                    reports.lock().unwrap().push(report.clone().into_owned());
+                })
+            };
+
+            let opts = OptsBuilder::from_opts(get_opts()).progress_handler(Some(handler));
+            let mut conn = Conn::new(opts).unwrap();
+
+            if conn.0.mariadb_server_version.is_none() {
+                // progress reports are MariaDB only
+                return;
+            }
+
+            assert!(conn
+                .0
+                .capability_flags
+                .contains(CapabilityFlags::CLIENT_PROGRESS_OBSOLETE));
+
+            conn.query_drop("SET SESSION progress_report_time = 1")
+                .unwrap();
+            conn.query_drop("CREATE TEMPORARY TABLE mysql.tbl(a INT)")
+                .unwrap();
+            conn.query_drop("INSERT INTO mysql.tbl(a) SELECT seq FROM seq_1_to_2000000")
+                .ok();
+            conn.query_drop("ALTER TABLE mysql.tbl ADD COLUMN b INT DEFAULT 1")
+                .unwrap();
+
+            for report in reports.lock().unwrap().iter() {
+                assert!(report.stage() >= 1);
+                assert!(report.stage() <= report.max_stage());
+                assert!(report.progress() <= 100_000);
+            }
+        }
+
+        #[test]
+        fn should_query_credentials_provider_at_every_connect() {
+            use std::sync::atomic::{AtomicUsize, Ordering};
+
+            use crate::{Credentials, CredentialsError, CredentialsProvider};
+
+            #[derive(Debug)]
+            struct Provider {
+                opts: Opts,
+                calls: Arc<AtomicUsize>,
+            }
+
+            impl CredentialsProvider for Provider {
+                fn get_credentials(&self) -> std::result::Result<Credentials, CredentialsError> {
+                    self.calls.fetch_add(1, Ordering::SeqCst);
+                    Ok(Credentials::new(self.opts.get_user(), self.opts.get_pass()))
+                }
+            }
+
+            #[derive(Debug)]
+            struct FailingProvider;
+
+            impl CredentialsProvider for FailingProvider {
+                fn get_credentials(&self) -> std::result::Result<Credentials, CredentialsError> {
+                    Err(CredentialsError::new("token service is unavailable"))
+                }
+            }
+
+            let calls = Arc::new(AtomicUsize::new(0));
+            let opts = OptsBuilder::from_opts(get_opts())
+                .pass(Some("wrong password"))
+                .credentials_provider(Provider {
+                    opts: get_opts(),
+                    calls: calls.clone(),
+                });
+            let mut conn = Conn::new(opts).unwrap();
+            let calls_after_connect = calls.load(Ordering::SeqCst);
+            assert!(calls_after_connect >= 1);
+
+            conn.hard_reset().unwrap();
+            assert_eq!(calls.load(Ordering::SeqCst), calls_after_connect + 1);
+
+            let opts = OptsBuilder::from_opts(get_opts()).credentials_provider(FailingProvider);
+            match Conn::new(opts) {
+                Err(crate::Error::CredentialsError(err)) => {
+                    assert_eq!(err.into_inner().to_string(), "token service is unavailable");
+                }
+                _ => panic!("expected CredentialsError"),
+            }
+        }
+
         #[test]
         fn should_reset_connection() {
             let mut conn = Conn::new(get_opts()).unwrap();
@@ -1736,6 +2613,318 @@ mod test {
                 .unwrap_err();
         }
 
+        #[test]
+        fn should_fall_back_or_compress_with_zstd() {
+            let opts = OptsBuilder::from_opts(get_opts()).zstd_compress(Some(5));
+            let mut conn = Conn::new(opts).unwrap();
+
+            let is_zstd = conn
+                .0
+                .capability_flags
+                .contains(CapabilityFlags::CLIENT_ZSTD_COMPRESSION_ALGORITHM);
+            if is_zstd {
+                assert!(!conn
+                    .0
+                    .capability_flags
+                    .contains(CapabilityFlags::CLIENT_COMPRESS));
+            }
+            // zstd is never requested without the `zstd` feature
+            assert!(cfg!(feature = "zstd") || !is_zstd);
+
+            // both compressed and uncompressed packets
+            let long = "x".repeat(100_000);
+            let value: Option<(u8, String)> = conn.exec_first("SELECT ?, ?", (1, &long)).unwrap();
+            assert_eq!(value, Some((1, long.clone())));
+            let value: Option<String> = conn
+                .query_first(format!("SELECT REPEAT('{}', 400)", &long[..100]))
+                .unwrap();
+            assert_eq!(value.map(|x| x.len()), Some(40_000));
+        }
+
+        #[test]
+        fn should_track_session_state() {
+            let opts = OptsBuilder::from_opts(get_opts()).track_session_state(true);
+            let mut conn = Conn::new(opts).unwrap();
+
+            conn.query_drop("USE mysql").unwrap();
+            assert_eq!(conn.session_state().schema(), Some("mysql"));
+
+            conn.query_drop("SET @@SESSION.sql_select_limit = 100")
+                .unwrap();
+            conn.query_drop("SELECT 1").unwrap();
+            assert_eq!(
+                conn.session_state().system_variable("sql_select_limit"),
+                Some("100")
+            );
+
+            let mut tx = conn.start_transaction(TxOpts::default()).unwrap();
+            let result = tx.query_iter("SELECT 1").unwrap();
+            drop(result);
+            let state = tx.session_state().transaction_state().cloned().unwrap();
+            assert!(state.is_active());
+            assert!(state.is_explicit());
+            tx.commit().unwrap();
+
+            let state = conn.session_state().transaction_state().unwrap();
+            assert!(!state.is_active());
+            // schema is kept while session variables are reset
+            conn.reset().unwrap();
+            assert_eq!(conn.session_state().schema(), Some("mysql"));
+            assert_eq!(
+                conn.session_state().system_variable("sql_select_limit"),
+                None
+            );
+
+            // trackers are enabled again after the reset
+            conn.query_drop("SET @@SESSION.sql_select_limit = 200")
+                .unwrap();
+            assert_eq!(
+                conn.session_state().system_variable("sql_select_limit"),
+                Some("200")
+            );
+
+            // GTIDs are reported only if binary logging (and GTID mode on MySql) is enabled
+            let gtids_enabled = if conn.0.mariadb_server_version.is_some() {
+                conn.query_first::<bool, _>("SELECT @@GLOBAL.log_bin")
+                    .unwrap()
+                    .unwrap()
+            } else {
+                conn.query_first::<String, _>("SELECT @@GLOBAL.gtid_mode")
+                    .unwrap()
+                    .unwrap()
+                    == "ON"
+            };
+            if gtids_enabled {
+                conn.query_drop("DROP TABLE IF EXISTS mysql.no_such_table")
+                    .unwrap();
+                assert!(conn.session_state().last_gtids().is_some());
+            }
+        }
+
+        #[test]
+        fn should_enable_supported_session_trackers() {
+            assert_eq!(track_session_state_query(Some((5, 6, 51)), None), None);
+            assert_eq!(
+                track_session_state_query(Some((5, 5, 5)), Some((10, 1, 48))),
+                None
+            );
+            assert_eq!(
+                track_session_state_query(Some((5, 7, 5)), None).unwrap(),
+                "SET @@SESSION.session_track_schema = ON, \
+                 @@SESSION.session_track_system_variables = '*', \
+                 @@SESSION.session_track_state_change = ON"
+            );
+            assert_eq!(
+                track_session_state_query(Some((5, 5, 5)), Some((10, 2, 2))).unwrap(),
+                "SET @@SESSION.session_track_schema = ON, \
+                 @@SESSION.session_track_system_variables = '*', \
+                 @@SESSION.session_track_state_change = ON"
+            );
+            assert!(track_session_state_query(Some((8, 0, 30)), None)
+                .unwrap()
+                .ends_with("@@SESSION.session_track_gtids = OWN_GTID"));
+            assert!(
+                !track_session_state_query(Some((5, 5, 5)), Some((10, 6, 0)))
+                    .unwrap()
+                    .contains("session_track_gtids")
+            );
+        }
+
+        #[test]
+        fn should_wait_for_gtid_set() {
+            let mut conn = Conn::new(get_opts()).unwrap();
+            let (executed, missing) = if conn.0.mariadb_server_version.is_some() {
+                let executed: String = conn
+                    .query_first("SELECT @@GLOBAL.gtid_current_pos")
+                    .unwrap()
+                    .unwrap();
+                (executed, "0-1-1000000000")
+            } else {
+                let gtid_mode: String = conn
+                    .query_first("SELECT @@GLOBAL.gtid_mode")
+                    .unwrap()
+                    .unwrap();
+                if gtid_mode != "ON" {
+                    return;
+                }
+                let executed: String = conn
+                    .query_first("SELECT @@GLOBAL.gtid_executed")
+                    .unwrap()
+                    .unwrap();
+                (
+                    executed,
+                    "3E11FA47-71CA-11E1-9E33-C80AA9429562:1-1000000000",
+                )
+            };
+
+            assert_eq!(
+                conn.wait_for_gtid_set(&executed, Some(Duration::from_secs(1)))
+                    .unwrap(),
+                GtidWaitResult::Reached
+            );
+            assert_eq!(
+                conn.wait_for_gtid_set(&executed, None).unwrap(),
+                GtidWaitResult::Reached
+            );
+            assert_eq!(
+                conn.wait_for_gtid_set(missing, Some(Duration::from_millis(100)))
+                    .unwrap(),
+                GtidWaitResult::TimedOut
+            );
+            // zero timeout doesn't wait
+            assert_eq!(
+                conn.wait_for_gtid_set(&executed, Some(Duration::from_secs(0)))
+                    .unwrap(),
+                GtidWaitResult::Reached
+            );
+            assert_eq!(
+                conn.wait_for_gtid_set(missing, Some(Duration::from_secs(0)))
+                    .unwrap(),
+                GtidWaitResult::TimedOut
+            );
+        }
+
+        #[test]
+        fn should_send_query_attributes() {
+            let mut conn = Conn::new(get_opts()).unwrap();
+            if !conn
+                .0
+                .capability_flags
+                .contains(CapabilityFlags::CLIENT_QUERY_ATTRIBUTES)
+            {
+                return;
+            }
+            // the component may be installed already
+            let _ = conn.query_drop("INSTALL COMPONENT 'file://component_query_attributes'");
+
+            let value: Option<Option<String>> = conn
+                .with_query_attributes(&[("foo", "bar"), ("baz", "qux")])
+                .query_first("SELECT mysql_query_attribute_string('baz')")
+                .unwrap();
+            assert_eq!(value, Some(Some("qux".into())));
+
+            let value: Option<(u8, Option<String>)> = conn
+                .with_query_attributes(&[("foo", "bar")])
+                .exec_first("SELECT ?, mysql_query_attribute_string('foo')", (42,))
+                .unwrap();
+            assert_eq!(value, Some((42, Some("bar".into()))));
+
+            // attributes are cleared once the wrapper is dropped
+            let value: Option<Option<String>> = conn
+                .query_first("SELECT mysql_query_attribute_string('foo')")
+                .unwrap();
+            assert_eq!(value, Some(None));
+            let value: Option<u8> = conn.exec_first("SELECT ?", (1,)).unwrap();
+            assert_eq!(value, Some(1));
+        }
+
+        #[test]
+        fn should_fetch_rows_via_cursor() {
+            let mut conn = Conn::new(get_opts()).unwrap();
+            conn.query_drop("CREATE TEMPORARY TABLE mysql.tbl(id INT NOT NULL PRIMARY KEY)")
+                .unwrap();
+            conn.exec_batch(
+                "INSERT INTO mysql.tbl (id) VALUES (?)",
+                (0..10).map(|x| (x,)),
+            )
+            .unwrap();
+
+            let stmt = conn
+                .prep("SELECT id FROM mysql.tbl WHERE id >= ? ORDER BY id")
+                .unwrap();
+            let ids = conn
+                .exec_cursor(&stmt, (2,), 3)
+                .unwrap()
+                .map(|row| from_row::<u32>(row.unwrap()))
+                .collect::<Vec<_>>();
+            assert_eq!(ids, (2..10).collect::<Vec<_>>());
+
+            // cursor is closed if dropped before exhausted
+            let mut cursor = conn.exec_cursor(&stmt, (0,), 2).unwrap();
+            assert_eq!(from_row::<u32>(cursor.next().unwrap().unwrap()), 0);
+            drop(cursor);
+            assert_eq!(conn.exec_first::<u32, _, _>(&stmt, (9,)).unwrap(), Some(9));
+
+            // no result set
+            let mut cursor = conn.exec_cursor("DO 1", (), 2).unwrap();
+            assert!(cursor.columns().is_empty());
+            assert!(cursor.next().is_none());
+        }
+
+        #[test]
+        #[cfg(not(target_os = "wasi"))]
+        fn should_kill_query_after_deadline() {
+            let mut conn = Conn::new(get_opts()).unwrap();
+
+            let deadline = Instant::now() + Duration::from_millis(500);
+            let result = conn.with_deadline(deadline, |conn| {
+                conn.query::<u8, _>("SELECT SLEEP(10) UNION ALL SELECT 2")
+            });
+            match result {
+                Err(DriverError(QueryTimeout)) => (),
+                _ => panic!("QueryTimeout error expected"),
+            }
+            assert_eq!(conn.query_first::<u8, _>("SELECT 1").unwrap(), Some(1));
+
+            let deadline = Instant::now() + Duration::from_secs(10);
+            let result = conn.with_deadline(deadline, |conn| conn.query_first::<u8, _>("SELECT 2"));
+            assert_eq!(result.unwrap(), Some(2));
+
+            // interrupted `SLEEP` returns 1, but the deadline is still reported
+            let deadline = Instant::now() + Duration::from_millis(500);
+            let result = conn.with_deadline(deadline, |conn| {
+                conn.query_first::<u8, _>("SELECT SLEEP(10)")
+            });
+            match result {
+                Err(DriverError(QueryTimeout)) => (),
+                _ => panic!("QueryTimeout error expected"),
+            }
+        }
+
+        #[test]
+        fn should_not_copy_session_setup_to_side_opts() {
+            let opts = OptsBuilder::from_opts(get_opts())
+                .init(vec!["SET @a = 42"])
+                .track_session_state(true);
+            let conn = Conn::new(opts).unwrap();
+
+            let side_opts = conn.side_opts();
+            assert!(side_opts.get_init().is_empty());
+            assert!(side_opts.get_hooks().is_none());
+            assert!(!side_opts.get_track_session_state());
+        }
+
+        #[test]
+        fn should_cancel_query_from_another_thread() {
+            let mut conn = Conn::new(get_opts()).unwrap();
+            let handle = conn.cancel_handle();
+            assert_eq!(handle.connection_id(), conn.connection_id());
+
+            let canceller = spawn({
+                let handle = handle.clone();
+                move || {
+                    std::thread::sleep(Duration::from_millis(500));
+                    handle.kill_query()
+                }
+            });
+            let started = Instant::now();
+            // interrupted query either errors or returns early, depending on the server
+            for row in conn
+                .query_iter("SELECT 1 UNION ALL SELECT SLEEP(10)")
+                .unwrap()
+            {
+                let _ = row;
+            }
+            canceller.join().unwrap().unwrap();
+            assert!(started.elapsed() < Duration::from_secs(10));
+
+            // the connection is intact after `KILL QUERY`
+            assert_eq!(conn.query_first::<u8, _>("SELECT 1").unwrap(), Some(1));
+
+            handle.kill_connection().unwrap();
+            assert!(conn.query_drop("SELECT 1").is_err());
+        }
+
         #[test]
         fn prep_exec() {
             let mut conn = Conn::new(get_opts()).unwrap();
@@ -2026,6 +3215,28 @@ mod test {
             }
         }
 
+        #[test]
+        fn should_failover_to_the_next_host() {
+            let opts = get_opts();
+            let host = opts.get_ip_or_hostname().into_owned();
+            let port = opts.get_tcp_port();
+
+            let opts = OptsBuilder::from_opts(opts)
+                .prefer_socket(false)
+                .ip_or_hostname(Some("127.0.0.1"))
+                .tcp_port(55555)
+                .additional_hosts(vec![(host.clone(), port)]);
+            let mut conn = Conn::new(opts.clone()).unwrap();
+            assert!(conn.ping());
+            assert_eq!(conn.connected_host(), Some((host.clone(), port)));
+
+            conn.reset().unwrap();
+            assert_eq!(conn.connected_host(), Some((host, port)));
+
+            let opts = opts.additional_hosts(Vec::<(String, u16)>::new());
+            assert!(Conn::new(opts).is_err());
+        }
+
         #[test]
         fn should_set_additional_capabilities() {
             use crate::consts::CapabilityFlags;
@@ -2412,6 +3623,327 @@ mod test {
         }
     }
 
+    mod scripted_auth {
+        use std::{
+            fs,
+            io::{Read, Write},
+            net::TcpListener,
+            path::PathBuf,
+            process,
+            thread::{self, JoinHandle},
+        };
+
+        use super::super::{auth_plugin::test::PUBLIC_KEY, Conn, ConnInner};
+        use crate::{
+            consts::{CapabilityFlags, StatusFlags},
+            AuthPluginContext, AuthPluginHandler,
+            DriverError::{ClearPasswordOverInsecureConnection, ServerPublicKeyMismatch},
+            Error::DriverError,
+            OptsBuilder, Result,
+        };
+
+        const NONCE: &[u8] = b"0123456789abcdefghij";
+
+        /// Step of the server side of a scripted connection phase.
+        enum Step {
+            /// Server sends the given payload.
+            Send(Vec<u8>),
+            /// Server reads a packet sent by the client.
+            Recv,
+        }
+
+        /// Serves a single connection following the script.
+        ///
+        /// Returns the port and the handle, that joins to the payloads sent by the client.
+        fn serve(script: Vec<Step>) -> (u16, JoinHandle<Vec<Vec<u8>>>) {
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            let port = listener.local_addr().unwrap().port();
+            let handle = thread::spawn(move || {
+                let (mut stream, _) = listener.accept().unwrap();
+                let mut seq_id = 0_u8;
+                let mut received = Vec::new();
+                for step in script {
+                    match step {
+                        Step::Send(payload) => {
+                            let mut header = (payload.len() as u32).to_le_bytes();
+                            header[3] = seq_id;
+                            stream.write_all(&header).unwrap();
+                            stream.write_all(&payload).unwrap();
+                            seq_id = seq_id.wrapping_add(1);
+                        }
+                        Step::Recv => {
+                            let mut header = [0_u8; 4];
+                            stream.read_exact(&mut header).unwrap();
+                            let len = u32::from_le_bytes([header[0], header[1], header[2], 0]);
+                            let mut payload = vec![0_u8; len as usize];
+                            stream.read_exact(&mut payload).unwrap();
+                            seq_id = header[3].wrapping_add(1);
+                            received.push(payload);
+                        }
+                    }
+                }
+                received
+            });
+            (port, handle)
+        }
+
+        /// Runs the connection phase against the scripted server.
+        fn handshake(opts: OptsBuilder, port: u16) -> Result<()> {
+            let opts = opts
+                .ip_or_hostname(Some("127.0.0.1"))
+                .tcp_port(port)
+                .prefer_socket(false);
+            let mut conn = Conn(Box::new(ConnInner::empty(opts.into())));
+            conn.connect_stream()?;
+            conn.do_handshake()
+        }
+
+        fn handshake_packet(plugin: &str) -> Vec<u8> {
+            let capabilities = (CapabilityFlags::CLIENT_PROTOCOL_41
+                | CapabilityFlags::CLIENT_SECURE_CONNECTION
+                | CapabilityFlags::CLIENT_PLUGIN_AUTH)
+                .bits();
+            let mut payload = vec![10];
+            payload.extend_from_slice(b"8.0.30\0");
+            payload.extend_from_slice(&1_u32.to_le_bytes());
+            payload.extend_from_slice(&NONCE[..8]);
+            payload.push(0);
+            payload.extend_from_slice(&(capabilities as u16).to_le_bytes());
+            payload.push(255);
+            payload.extend_from_slice(&StatusFlags::SERVER_STATUS_AUTOCOMMIT.bits().to_le_bytes());
+            payload.extend_from_slice(&((capabilities >> 16) as u16).to_le_bytes());
+            payload.push(NONCE.len() as u8 + 1);
+            payload.extend_from_slice(&[0; 10]);
+            payload.extend_from_slice(&NONCE[8..]);
+            payload.push(0);
+            payload.extend_from_slice(plugin.as_bytes());
+            payload.push(0);
+            payload
+        }
+
+        fn auth_switch_packet(plugin: &str, data: &[u8]) -> Vec<u8> {
+            let mut payload = vec![0xfe];
+            payload.extend_from_slice(plugin.as_bytes());
+            payload.push(0);
+            payload.extend_from_slice(data);
+            payload
+        }
+
+        fn more_data_packet(data: &[u8]) -> Vec<u8> {
+            let mut payload = vec![0x01];
+            payload.extend_from_slice(data);
+            payload
+        }
+
+        fn ok_packet() -> Vec<u8> {
+            vec![0x00, 0x00, 0x00, 0x02, 0x00, 0x00, 0x00]
+        }
+
+        /// Writes the test public key into a temporary file.
+        fn public_key_file(name: &str) -> PathBuf {
+            let path = std::env::temp_dir().join(format!("{}-{}.pem", name, process::id()));
+            fs::write(&path, PUBLIC_KEY).unwrap();
+            path
+        }
+
+        /// Sends the user name, then answers every "more data" packet with reversed data.
+        #[derive(Debug)]
+        struct ReversingAuth;
+
+        impl AuthPluginHandler for ReversingAuth {
+            fn name(&self) -> &str {
+                "reversing_auth"
+            }
+
+            fn initial_response(&self, ctx: &AuthPluginContext<'_>) -> Result<Vec<u8>> {
+                Ok(ctx.user().unwrap_or_default().as_bytes().to_vec())
+            }
+
+            fn handle_more_data(
+                &self,
+                _ctx: &AuthPluginContext<'_>,
+                data: &[u8],
+            ) -> Result<Option<Vec<u8>>> {
+                match data {
+                    b"skip" => Ok(None),
+                    _ => Ok(Some(data.iter().rev().copied().collect())),
+                }
+            }
+        }
+
+        #[test]
+        fn should_run_multi_round_custom_auth() {
+            let (port, server) = serve(vec![
+                Step::Send(handshake_packet("reversing_auth")),
+                Step::Recv,
+                Step::Send(more_data_packet(b"abc")),
+                Step::Recv,
+                Step::Send(more_data_packet(b"skip")),
+                Step::Send(more_data_packet(b"xyz")),
+                Step::Recv,
+                Step::Send(ok_packet()),
+            ]);
+
+            let opts = OptsBuilder::new()
+                .user(Some("root"))
+                .auth_plugin_handler(ReversingAuth);
+            handshake(opts, port).unwrap();
+
+            let received = server.join().unwrap();
+            assert!(received[0].ends_with(b"\x04rootreversing_auth\0"));
+            assert_eq!(received[1], b"cba");
+            assert_eq!(received[2], b"zyx");
+        }
+
+        #[test]
+        fn should_not_load_public_key_for_custom_auth_plugin() {
+            let (port, server) = serve(vec![
+                Step::Send(handshake_packet("reversing_auth")),
+                Step::Recv,
+                Step::Send(ok_packet()),
+            ]);
+
+            let opts = OptsBuilder::new()
+                .user(Some("root"))
+                .server_public_key_path(Some(std::path::Path::new("/nonexistent/key.pem")))
+                .auth_plugin_handler(ReversingAuth);
+            handshake(opts, port).unwrap();
+
+            server.join().unwrap();
+        }
+
+        #[test]
+        fn should_switch_to_custom_auth_plugin() {
+            let (port, server) = serve(vec![
+                Step::Send(handshake_packet("mysql_native_password")),
+                Step::Recv,
+                Step::Send(auth_switch_packet("reversing_auth", NONCE)),
+                Step::Recv,
+                Step::Send(more_data_packet(b"abc")),
+                Step::Recv,
+                Step::Send(ok_packet()),
+            ]);
+
+            let opts = OptsBuilder::new()
+                .user(Some("root"))
+                .pass(Some("password"))
+                .auth_plugin_handler(ReversingAuth);
+            handshake(opts, port).unwrap();
+
+            let received = server.join().unwrap();
+            assert_eq!(received[1], b"root");
+            assert_eq!(received[2], b"cba");
+        }
+
+        #[test]
+        fn should_request_public_key_for_sha256_password() {
+            let (port, server) = serve(vec![
+                Step::Send(handshake_packet("sha256_password")),
+                Step::Recv,
+                Step::Send(more_data_packet(PUBLIC_KEY)),
+                Step::Recv,
+                Step::Send(ok_packet()),
+            ]);
+
+            let opts = OptsBuilder::new().user(Some("root")).pass(Some("password"));
+            handshake(opts, port).unwrap();
+
+            let received = server.join().unwrap();
+            assert!(received[0].ends_with(b"\x01\x01sha256_password\0"));
+            assert_eq!(received[1].len(), 256);
+        }
+
+        #[test]
+        fn should_switch_to_sha256_password() {
+            let mut nonce = NONCE.to_vec();
+            nonce.push(0);
+            let (port, server) = serve(vec![
+                Step::Send(handshake_packet("mysql_native_password")),
+                Step::Recv,
+                Step::Send(auth_switch_packet("sha256_password", &nonce)),
+                Step::Recv,
+                Step::Send(more_data_packet(PUBLIC_KEY)),
+                Step::Recv,
+                Step::Send(ok_packet()),
+            ]);
+
+            let opts = OptsBuilder::new().user(Some("root")).pass(Some("password"));
+            handshake(opts, port).unwrap();
+
+            let received = server.join().unwrap();
+            assert_eq!(received[1], [0x01]);
+            assert_eq!(received[2].len(), 256);
+        }
+
+        #[test]
+        fn should_compare_pinned_key_with_caching_sha2_password_server_key() {
+            let path = public_key_file("caching_sha2_password_pinned_key");
+            let (port, server) = serve(vec![
+                Step::Send(handshake_packet("caching_sha2_password")),
+                Step::Recv,
+                Step::Send(more_data_packet(&[0x04])),
+                Step::Recv,
+                Step::Send(more_data_packet(PUBLIC_KEY)),
+                Step::Recv,
+                Step::Send(ok_packet()),
+            ]);
+
+            let opts = OptsBuilder::new()
+                .user(Some("root"))
+                .pass(Some("password"))
+                .server_public_key_path(Some(path.clone()));
+            handshake(opts, port).unwrap();
+
+            let received = server.join().unwrap();
+            assert_eq!(received[1], [0x02]);
+            assert_eq!(received[2].len(), 256);
+            fs::remove_file(path).unwrap();
+        }
+
+        #[test]
+        fn should_detect_caching_sha2_password_server_key_mismatch() {
+            let path = public_key_file("caching_sha2_password_key_mismatch");
+            let (port, server) = serve(vec![
+                Step::Send(handshake_packet("caching_sha2_password")),
+                Step::Recv,
+                Step::Send(more_data_packet(&[0x04])),
+                Step::Recv,
+                Step::Send(more_data_packet(
+                    b"-----BEGIN PUBLIC KEY-----\nAAAA\n-----END PUBLIC KEY-----\n",
+                )),
+            ]);
+
+            let opts = OptsBuilder::new()
+                .user(Some("root"))
+                .pass(Some("password"))
+                .server_public_key_path(Some(path.clone()));
+            assert!(matches!(
+                handshake(opts, port),
+                Err(DriverError(ServerPublicKeyMismatch))
+            ));
+
+            server.join().unwrap();
+            fs::remove_file(path).unwrap();
+        }
+
+        #[test]
+        fn should_refuse_to_switch_to_clear_password_over_tcp() {
+            let (port, server) = serve(vec![
+                Step::Send(handshake_packet("mysql_native_password")),
+                Step::Recv,
+                Step::Send(auth_switch_packet("mysql_clear_password", NONCE)),
+            ]);
+
+            let opts = OptsBuilder::new().user(Some("root")).pass(Some("password"));
+            assert!(matches!(
+                handshake(opts, port),
+                Err(DriverError(ClearPasswordOverInsecureConnection))
+            ));
+
+            server.join().unwrap();
+        }
+    }
+
     #[cfg(feature = "nightly")]
     mod bench {
         use test;
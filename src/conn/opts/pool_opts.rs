@@ -0,0 +1,174 @@
+// Copyright (c) 2021 Anatoly Ikorsky
+//
+// Licensed under the Apache License, Version 2.0
+// <LICENSE-APACHE or http://www.apache.org/licenses/LICENSE-2.0> or the MIT
+// license <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. All files in the project carrying such notice may not be copied,
+// modified, or distributed except according to those terms.
+
+use std::{fmt, sync::Arc, time::Duration};
+
+use crate::{conn::arc_by_ptr::ArcByPtr, Conn, Result};
+
+type CleanupFn = dyn Fn(&mut Conn) -> Result<()> + Send + Sync;
+
+/// Callback, that cleans up a connection before it is returned to a pool
+/// (see [`PoolOpts::with_cleanup_handler`]).
+///
+/// Note that the callback may be called concurrently for different connections.
+///
+/// ```rust
+/// # use mysql::{prelude::*, CleanupHandler, PoolOpts};
+/// let pool_opts = PoolOpts::default().with_cleanup_handler(Some(CleanupHandler::new(|conn| {
+///     conn.query_drop("DO RELEASE_ALL_LOCKS()")
+/// })));
+/// ```
+#[derive(Clone, PartialEq, Eq)]
+pub struct CleanupHandler(pub(crate) ArcByPtr<CleanupFn>);
+
+impl CleanupHandler {
+    /// Creates a handler from the given callback.
+    pub fn new<F>(f: F) -> Self
+    where
+        F: Fn(&mut Conn) -> Result<()> + Send + Sync + 'static,
+    {
+        CleanupHandler(ArcByPtr(Arc::new(f)))
+    }
+}
+
+impl fmt::Debug for CleanupHandler {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "CleanupHandler(...)")
+    }
+}
+
+/// Connection pool options.
+///
+/// Only used by a [`crate::Pool`], ignored by a standalone [`crate::Conn`].
+///
+/// ```rust
+/// # use mysql::{OptsBuilder, PoolOpts};
+/// # use std::time::Duration;
+/// let opts = OptsBuilder::new().pool_opts(
+///     PoolOpts::default()
+///         .with_max_lifetime(Some(Duration::from_secs(30 * 60)))
+///         .with_idle_timeout(Some(Duration::from_secs(5 * 60))),
+/// );
+/// ```
+#[derive(Debug, Clone, Eq, PartialEq, Default)]
+pub struct PoolOpts {
+    max_lifetime: Option<Duration>,
+    idle_timeout: Option<Duration>,
+    maintenance_interval: Option<Duration>,
+    reset_connection: bool,
+    cleanup_handler: Option<CleanupHandler>,
+    max_waiters: Option<usize>,
+}
+
+impl PoolOpts {
+    /// Maximum lifetime of a pooled connection (defaults to `None`).
+    ///
+    /// A connection older than this will be closed, instead of being handed out
+    /// or returned to the pool. Use it to recycle connections before they are
+    /// reaped by the server (`wait_timeout`) or a proxy.
+    pub fn with_max_lifetime(mut self, max_lifetime: Option<Duration>) -> Self {
+        self.max_lifetime = max_lifetime;
+        self
+    }
+
+    /// Maximum time a connection may sit idle in the pool (defaults to `None`).
+    ///
+    /// A connection that was idle for longer than this will be closed instead
+    /// of being handed out.
+    pub fn with_idle_timeout(mut self, idle_timeout: Option<Duration>) -> Self {
+        self.idle_timeout = idle_timeout;
+        self
+    }
+
+    /// Interval of the background pool maintenance (defaults to `None`, i.e. disabled).
+    ///
+    /// If defined, then a pool will spawn a thread, that will periodically:
+    ///
+    /// *   ping idle connections and close broken ones;
+    /// *   close idle connections, that exceeded `max_lifetime` or `idle_timeout`;
+    /// *   close connections above `min`, that were idle for the whole interval;
+    /// *   create new connections to keep at least `min` of them.
+    ///
+    /// The thread stops as soon as the last clone of the pool is dropped.
+    ///
+    /// **Note:** Ignored on WASI, because it requires threads (pool is built
+    /// without the background maintenance).
+    pub fn with_maintenance_interval(mut self, maintenance_interval: Option<Duration>) -> Self {
+        self.maintenance_interval = maintenance_interval;
+        self
+    }
+
+    /// Whether to reset a connection before it is returned to a pool (defaults to `false`).
+    ///
+    /// If `true`, then [`Conn::reset`] will be called for every connection returned to a pool,
+    /// so that session state (session variables, temporary tables, user locks, open
+    /// transactions, etc.) is not leaked to the next user. Commands given by `Opts::get_init`
+    /// are executed again after the reset.
+    ///
+    /// The connection will be closed if the reset fails.
+    ///
+    /// **Note:** the reset is performed by the thread that drops the `PooledConn`.
+    pub fn with_reset_connection(mut self, reset_connection: bool) -> Self {
+        self.reset_connection = reset_connection;
+        self
+    }
+
+    /// Callback, that cleans up a connection before it is returned to a pool
+    /// (defaults to `None`).
+    ///
+    /// The callback is called after the reset (see [`PoolOpts::with_reset_connection`]),
+    /// if any. The connection will be closed if the callback returns an error.
+    pub fn with_cleanup_handler(mut self, cleanup_handler: Option<CleanupHandler>) -> Self {
+        self.cleanup_handler = cleanup_handler;
+        self
+    }
+
+    /// Maximum number of threads waiting for a connection (defaults to `None`, i.e. unbounded).
+    ///
+    /// Threads blocked on an exhausted pool are served in arrival order. Once the queue
+    /// is full, further checkouts fail immediately with `DriverError::PoolQueueFull`
+    /// instead of waiting.
+    pub fn with_max_waiters(mut self, max_waiters: Option<usize>) -> Self {
+        self.max_waiters = max_waiters;
+        self
+    }
+
+    /// Maximum lifetime of a pooled connection (see [`PoolOpts::with_max_lifetime`]).
+    pub fn max_lifetime(&self) -> Option<Duration> {
+        self.max_lifetime
+    }
+
+    /// Maximum idle time of a pooled connection (see [`PoolOpts::with_idle_timeout`]).
+    pub fn idle_timeout(&self) -> Option<Duration> {
+        self.idle_timeout
+    }
+
+    /// Interval of the background pool maintenance
+    /// (see [`PoolOpts::with_maintenance_interval`]).
+    pub fn maintenance_interval(&self) -> Option<Duration> {
+        self.maintenance_interval
+    }
+
+    /// Whether to reset a connection before it is returned to a pool
+    /// (see [`PoolOpts::with_reset_connection`]).
+    pub fn reset_connection(&self) -> bool {
+        self.reset_connection
+    }
+
+    /// Callback, that cleans up a connection before it is returned to a pool
+    /// (see [`PoolOpts::with_cleanup_handler`]).
+    pub fn cleanup_handler(&self) -> Option<&CleanupHandler> {
+        self.cleanup_handler.as_ref()
+    }
+
+    /// Maximum number of threads waiting for a connection
+    /// (see [`PoolOpts::with_max_waiters`]).
+    pub fn max_waiters(&self) -> Option<usize> {
+        self.max_waiters
+    }
+}
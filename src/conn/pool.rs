@@ -7,27 +7,50 @@
 // modified, or distributed except according to those terms.
 
 use std::{
+    cmp,
     collections::VecDeque,
-    fmt,
+    fmt, mem,
     ops::Deref,
     sync::{
-        atomic::{AtomicUsize, Ordering},
-        Arc, Condvar, Mutex,
+        atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering},
+        mpsc::{sync_channel, Receiver, RecvTimeoutError, SyncSender},
+        Arc, Condvar, Mutex, Weak,
     },
+    thread,
     time::{Duration, Instant},
 };
 
 use crate::{
     conn::query_result::{Binary, Text},
     prelude::*,
-    Conn, DriverError, Error, LocalInfileHandler, Opts, Params, QueryResult, Result, Statement,
-    Transaction, TxOpts,
+    Conn, ConnHooks, Cursor, DriverError, Error, GtidWaitResult, LocalInfileHandler, Opts, Params,
+    PoolOpts, ProgressHandler, QueryResult, Result, RetryPolicy, Statement, Transaction, TxOpts,
+    Value, WithQueryAttributes,
 };
 
+/// Connection that sits in a pool.
+#[derive(Debug)]
+struct IdleConn {
+    conn: Conn,
+    /// When the connection was returned to the pool.
+    since: Instant,
+}
+
+impl IdleConn {
+    fn new(conn: Conn) -> Self {
+        IdleConn {
+            conn,
+            since: Instant::now(),
+        }
+    }
+}
+
 #[derive(Debug)]
 struct InnerPool {
     opts: Opts,
-    pool: VecDeque<Conn>,
+    pool: VecDeque<IdleConn>,
+    /// Threads waiting for a connection, in arrival order.
+    waiters: VecDeque<Arc<Condvar>>,
 }
 
 impl InnerPool {
@@ -38,6 +61,7 @@ impl InnerPool {
         let mut pool = InnerPool {
             opts,
             pool: VecDeque::with_capacity(max),
+            waiters: VecDeque::new(),
         };
         for _ in 0..min {
             pool.new_conn()?;
@@ -47,12 +71,84 @@ impl InnerPool {
     fn new_conn(&mut self) -> Result<()> {
         match Conn::new(self.opts.clone()) {
             Ok(conn) => {
-                self.pool.push_back(conn);
+                self.pool.push_back(IdleConn::new(conn));
                 Ok(())
             }
             Err(err) => Err(err),
         }
     }
+
+    /// Returns `true` if the connection has exceeded `max_lifetime`.
+    fn is_expired(&self, conn: &Conn) -> bool {
+        self.opts
+            .get_pool_opts()
+            .max_lifetime()
+            .map(|max_lifetime| conn.connection_age() > max_lifetime)
+            .unwrap_or(false)
+    }
+
+    /// Returns `true` if the idle connection has exceeded `max_lifetime` or `idle_timeout`.
+    fn is_stale(&self, idle: &IdleConn) -> bool {
+        self.is_expired(&idle.conn)
+            || self
+                .opts
+                .get_pool_opts()
+                .idle_timeout()
+                .map(|idle_timeout| idle.since.elapsed() > idle_timeout)
+                .unwrap_or(false)
+    }
+
+    /// Wakes up the first thread in the waiters queue (if any).
+    fn notify_waiter(&self) {
+        if let Some(waiter) = self.waiters.front() {
+            waiter.notify_one();
+        }
+    }
+
+    /// Wakes up every thread in the waiters queue.
+    fn notify_all_waiters(&self) {
+        for waiter in &self.waiters {
+            waiter.notify_one();
+        }
+    }
+
+    /// Removes the given waiter from the queue and passes the turn to the next one.
+    fn leave_queue(&mut self, waiter: &Arc<Condvar>) {
+        self.waiters.retain(|other| !Arc::ptr_eq(other, waiter));
+        self.notify_waiter();
+    }
+}
+
+/// Upper bounds of checkout wait time histogram buckets (the last bucket is unbounded).
+/// Maximum number of connections vetoed by [`ConnHooks::before_acquire`] during a checkout.
+const MAX_ACQUIRE_VETOES: usize = 10;
+
+const CHECKOUT_WAIT_BUCKETS: [Duration; 5] = [
+    Duration::from_millis(1),
+    Duration::from_millis(10),
+    Duration::from_millis(100),
+    Duration::from_secs(1),
+    Duration::from_secs(10),
+];
+
+/// Pool counters (see [`PoolStats`]).
+#[derive(Debug, Default)]
+struct PoolCounters {
+    created: AtomicU64,
+    closed: AtomicU64,
+    checkout_wait: [AtomicU64; CHECKOUT_WAIT_BUCKETS.len() + 1],
+    timeouts: AtomicU64,
+    failed_health_checks: AtomicU64,
+}
+
+impl PoolCounters {
+    fn record_checkout_wait(&self, wait: Duration) {
+        let bucket = CHECKOUT_WAIT_BUCKETS
+            .iter()
+            .position(|bound| wait <= *bound)
+            .unwrap_or(CHECKOUT_WAIT_BUCKETS.len());
+        self.checkout_wait[bucket].fetch_add(1, Ordering::Relaxed);
+    }
 }
 
 struct ArcedPool {
@@ -60,6 +156,202 @@ struct ArcedPool {
     min: usize,
     max: usize,
     count: AtomicUsize,
+    counters: PoolCounters,
+    pool_opts: PoolOpts,
+    hooks: Option<Arc<dyn ConnHooks>>,
+    /// `true` if the pool was closed (see [`Pool::drain`]).
+    closed: AtomicBool,
+    /// Maintenance thread (if any) stops once this sender is dropped.
+    _maintenance_stop: Option<SyncSender<()>>,
+}
+
+impl ArcedPool {
+    /// Accounts for a connection that was closed or detached from the pool.
+    fn conn_closed(&self) {
+        self.count.fetch_sub(1, Ordering::SeqCst);
+        self.counters.closed.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Wakes up the first waiting thread, so that it could take a returned connection
+    /// or a freed slot.
+    fn notify_waiter(&self) {
+        if let Ok(pool) = self.inner.0.lock() {
+            pool.notify_waiter();
+        }
+    }
+
+    /// Cleans up a connection before it is returned to the pool.
+    fn cleanup(&self, conn: &mut Conn) -> Result<()> {
+        if self.pool_opts.reset_connection() {
+            conn.reset()?;
+            conn.run_init()?;
+        }
+        if let Some(handler) = self.pool_opts.cleanup_handler() {
+            (*handler.0)(conn)?;
+        }
+        Ok(())
+    }
+
+    /// Performs a round of the background maintenance (see `PoolOpts::with_maintenance_interval`).
+    fn maintain(&self, interval: Duration) {
+        if self.closed.load(Ordering::SeqCst) {
+            return;
+        }
+
+        let inner_pool = &self.inner.0;
+
+        let (idle_count, opts) = match inner_pool.lock() {
+            Ok(pool) => (pool.pool.len(), pool.opts.clone()),
+            Err(_) => return,
+        };
+
+        // Every idle connection is checked once. Connections are pinged outside of the lock,
+        // so that concurrent checkouts are not blocked.
+        for _ in 0..idle_count {
+            let mut idle = {
+                let mut pool = match inner_pool.lock() {
+                    Ok(pool) => pool,
+                    Err(_) => return,
+                };
+                let idle = match pool.pool.pop_front() {
+                    Some(idle) => idle,
+                    None => break,
+                };
+                let is_redundant = self.count.load(Ordering::SeqCst) > self.min
+                    && idle.since.elapsed() >= interval;
+                if is_redundant || pool.is_stale(&idle) {
+                    self.conn_closed();
+                    pool.notify_waiter();
+                    drop(pool);
+                    continue;
+                }
+                idle
+            };
+
+            if !idle.conn.ping() {
+                self.counters
+                    .failed_health_checks
+                    .fetch_add(1, Ordering::Relaxed);
+                self.conn_closed();
+                self.notify_waiter();
+                continue;
+            }
+
+            match inner_pool.lock() {
+                Ok(mut pool) => {
+                    pool.pool.push_back(idle);
+                    pool.notify_waiter();
+                }
+                Err(_) => return,
+            }
+        }
+
+        // Refill up to `min` connections.
+        loop {
+            let count = self.count.load(Ordering::SeqCst);
+            if count >= self.min {
+                break;
+            }
+            if self
+                .count
+                .compare_exchange(count, count + 1, Ordering::SeqCst, Ordering::SeqCst)
+                .is_err()
+            {
+                continue;
+            }
+            match Conn::new(opts.clone()) {
+                Ok(conn) => {
+                    self.counters.created.fetch_add(1, Ordering::Relaxed);
+                    match inner_pool.lock() {
+                        Ok(mut pool) => {
+                            pool.pool.push_back(IdleConn::new(conn));
+                            pool.notify_waiter();
+                        }
+                        Err(_) => return,
+                    }
+                }
+                Err(_) => {
+                    // will retry on the next round
+                    self.count.fetch_sub(1, Ordering::SeqCst);
+                    break;
+                }
+            }
+        }
+    }
+}
+
+/// Body of the pool maintenance thread.
+fn run_maintenance(pool: Weak<ArcedPool>, stop: Receiver<()>, interval: Duration) {
+    while let Err(RecvTimeoutError::Timeout) = stop.recv_timeout(interval) {
+        match pool.upgrade() {
+            Some(pool) => pool.maintain(interval),
+            None => break,
+        }
+    }
+}
+
+/// Snapshot of a [`Pool`] state (see [`Pool::stats`]).
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct PoolStats {
+    idle: usize,
+    in_use: usize,
+    created: u64,
+    closed: u64,
+    waiters: usize,
+    checkout_wait: [u64; CHECKOUT_WAIT_BUCKETS.len() + 1],
+    timeouts: u64,
+    failed_health_checks: u64,
+}
+
+impl PoolStats {
+    /// Number of connections sitting in the pool.
+    pub fn idle(&self) -> usize {
+        self.idle
+    }
+
+    /// Number of connections currently checked out.
+    pub fn in_use(&self) -> usize {
+        self.in_use
+    }
+
+    /// Total number of connections created by the pool.
+    pub fn total_created(&self) -> u64 {
+        self.created
+    }
+
+    /// Total number of connections closed by the pool
+    /// (including connections detached via [`PooledConn::unwrap`]).
+    pub fn total_closed(&self) -> u64 {
+        self.closed
+    }
+
+    /// Number of threads currently waiting for a connection.
+    pub fn waiters(&self) -> usize {
+        self.waiters
+    }
+
+    /// Histogram of time spent by successful checkouts (`get_conn` and friends).
+    ///
+    /// Returns `(upper_bound, count)` pairs, where `None` bound stands for infinity.
+    pub fn checkout_wait_histogram(&self) -> Vec<(Option<Duration>, u64)> {
+        CHECKOUT_WAIT_BUCKETS
+            .iter()
+            .copied()
+            .map(Some)
+            .chain(std::iter::once(None))
+            .zip(self.checkout_wait.iter().copied())
+            .collect()
+    }
+
+    /// Number of checkouts that failed with `DriverError::Timeout`.
+    pub fn timeouts(&self) -> u64 {
+        self.timeouts
+    }
+
+    /// Number of checked out connections that failed the health check (`Conn::ping`).
+    pub fn failed_health_checks(&self) -> u64 {
+        self.failed_health_checks
+    }
 }
 
 /// `Pool` serves to provide you with a [`PooledConn`](struct.PooledConn.html)'s.
@@ -69,6 +361,9 @@ struct ArcedPool {
 /// `Pool` will hold at least `min` connections and will create as many as `max`
 /// connections with possible overhead of one connection per alive thread.
 ///
+/// If all `max` connections are in use, then callers are blocked and served in arrival order
+/// (see `PoolOpts::with_max_waiters` to limit the queue length).
+///
 /// Example of multithreaded `Pool` usage:
 ///
 /// ```rust
@@ -116,62 +411,164 @@ impl Pool {
         timeout_ms: Option<u32>,
         call_ping: bool,
     ) -> Result<PooledConn> {
+        if self.is_closed() {
+            return Err(DriverError::PoolClosed.into());
+        }
+
+        let checkout_start = Instant::now();
         let times = if let Some(timeout_ms) = timeout_ms {
             Some((Instant::now(), Duration::from_millis(timeout_ms.into())))
         } else {
             None
         };
 
-        let &(ref inner_pool, ref condvar) = &self.arced_pool.inner;
+        let inner_pool = &self.arced_pool.inner.0;
 
-        let conn = if self.use_cache {
-            if let Some(query) = stmt {
-                let mut id = None;
-                let mut pool = inner_pool.lock()?;
-                for (i, conn) in pool.pool.iter().rev().enumerate() {
-                    if conn.has_stmt(query.as_ref()) {
-                        id = Some(i);
-                        break;
+        let mut stmt = stmt;
+        let mut vetoes = 0;
+        let mut conn = loop {
+            let conn = if self.use_cache {
+                if let Some(query) = stmt.take() {
+                    let mut id = None;
+                    let mut pool = inner_pool.lock()?;
+                    // threads waiting for a connection are served first
+                    if pool.waiters.is_empty() {
+                        for (i, idle) in pool.pool.iter().rev().enumerate() {
+                            if idle.conn.has_stmt(query.as_ref()) {
+                                id = Some(i);
+                                break;
+                            }
+                        }
+                    }
+                    match id.and_then(|id| pool.pool.swap_remove_back(id)) {
+                        Some(idle) if pool.is_stale(&idle) => {
+                            self.arced_pool.conn_closed();
+                            pool.notify_waiter();
+                            None
+                        }
+                        idle => idle.map(|idle| idle.conn),
                     }
+                } else {
+                    None
                 }
-                id.and_then(|id| pool.pool.swap_remove_back(id))
             } else {
                 None
-            }
-        } else {
-            None
-        };
+            };
 
-        let mut conn = if let Some(conn) = conn {
-            conn
-        } else {
-            let mut pool = inner_pool.lock()?;
-            loop {
-                if let Some(conn) = pool.pool.pop_front() {
-                    drop(pool);
-                    break conn;
-                } else if self.arced_pool.count.load(Ordering::Relaxed) < self.arced_pool.max {
-                    pool.new_conn()?;
-                    self.arced_pool.count.fetch_add(1, Ordering::SeqCst);
-                } else {
+            let mut conn = if let Some(conn) = conn {
+                conn
+            } else {
+                let mut pool = inner_pool.lock()?;
+                // `Some` once this thread is in the waiters queue
+                let mut waiter: Option<Arc<Condvar>> = None;
+                let result: Result<Conn> = loop {
+                    if self.is_closed() {
+                        break Err(DriverError::PoolClosed.into());
+                    }
+                    // waiters are served in arrival order
+                    let is_first = match waiter {
+                        Some(ref waiter) => pool
+                            .waiters
+                            .front()
+                            .map(|first| Arc::ptr_eq(first, waiter))
+                            .unwrap_or(false),
+                        None => pool.waiters.is_empty(),
+                    };
+                    if is_first {
+                        if let Some(idle) = pool.pool.pop_front() {
+                            if pool.is_stale(&idle) {
+                                // stale connection is closed and replaced with a new one
+                                self.arced_pool.conn_closed();
+                                continue;
+                            }
+                            break Ok(idle.conn);
+                        } else if self.arced_pool.count.load(Ordering::Relaxed)
+                            < self.arced_pool.max
+                        {
+                            if let Err(err) = pool.new_conn() {
+                                break Err(err);
+                            }
+                            self.arced_pool.count.fetch_add(1, Ordering::SeqCst);
+                            self.arced_pool
+                                .counters
+                                .created
+                                .fetch_add(1, Ordering::Relaxed);
+                            continue;
+                        }
+                    }
+
+                    if waiter.is_none() {
+                        if let Some(max_waiters) = self.arced_pool.pool_opts.max_waiters() {
+                            if pool.waiters.len() >= max_waiters {
+                                break Err(DriverError::PoolQueueFull.into());
+                            }
+                        }
+                        pool.waiters.push_back(Arc::new(Condvar::new()));
+                        waiter = pool.waiters.back().cloned();
+                    }
+                    let condvar = waiter.as_deref().expect("queued above");
                     pool = if let Some((start, timeout)) = times {
-                        if start.elapsed() > timeout {
-                            return Err(DriverError::Timeout.into());
+                        let elapsed = start.elapsed();
+                        if elapsed >= timeout {
+                            self.arced_pool
+                                .counters
+                                .timeouts
+                                .fetch_add(1, Ordering::Relaxed);
+                            break Err(DriverError::Timeout.into());
                         }
-                        condvar.wait_timeout(pool, timeout)?.0
+                        condvar.wait_timeout(pool, timeout - elapsed)?.0
                     } else {
                         condvar.wait(pool)?
+                    };
+                };
+                if let Some(ref waiter) = waiter {
+                    pool.leave_queue(waiter);
+                }
+                drop(pool);
+                result?
+            };
+
+            if call_ping && self.check_health && !conn.ping() {
+                self.arced_pool
+                    .counters
+                    .failed_health_checks
+                    .fetch_add(1, Ordering::Relaxed);
+                // reconnected session needs the same setup as a new connection
+                if let Err(err) = conn.reset().and_then(|_| conn.run_init()) {
+                    self.arced_pool.conn_closed();
+                    self.arced_pool.notify_waiter();
+                    return Err(err);
+                }
+            }
+
+            if let Some(ref hooks) = self.arced_pool.hooks {
+                if !hooks.before_acquire(&mut conn) {
+                    // vetoed connection is closed and replaced with another one
+                    self.arced_pool.conn_closed();
+                    self.arced_pool.notify_waiter();
+                    vetoes += 1;
+                    if vetoes >= MAX_ACQUIRE_VETOES {
+                        return Err(DriverError::AcquireVetoed.into());
                     }
+                    if let Some((start, timeout)) = times {
+                        if start.elapsed() >= timeout {
+                            self.arced_pool
+                                .counters
+                                .timeouts
+                                .fetch_add(1, Ordering::Relaxed);
+                            return Err(DriverError::Timeout.into());
+                        }
+                    }
+                    continue;
                 }
             }
+
+            break conn;
         };
 
-        if call_ping && self.check_health && !conn.ping() {
-            if let Err(err) = conn.reset() {
-                self.arced_pool.count.fetch_sub(1, Ordering::SeqCst);
-                return Err(err);
-            }
-        }
+        self.arced_pool
+            .counters
+            .record_checkout_wait(checkout_start.elapsed());
 
         Ok(PooledConn {
             pool: self.clone(),
@@ -195,13 +592,43 @@ impl Pool {
         crate::Error: From<E>,
     {
         let pool = InnerPool::new(min, max, opts.try_into()?)?;
+        let pool_opts = pool.opts.get_pool_opts().clone();
+        let hooks = pool.opts.get_hooks().cloned();
+        // background maintenance requires threads, so it is disabled on WASI
+        let maintenance_interval = if cfg!(target_os = "wasi") {
+            None
+        } else {
+            pool_opts.maintenance_interval()
+        };
+        let (maintenance_stop, maintenance_stop_rx) = match maintenance_interval {
+            Some(_) => {
+                let (tx, rx) = sync_channel(0);
+                (Some(tx), Some(rx))
+            }
+            None => (None, None),
+        };
+        let arced_pool = Arc::new(ArcedPool {
+            inner: (Mutex::new(pool), Condvar::new()),
+            min,
+            max,
+            count: AtomicUsize::new(min),
+            counters: PoolCounters {
+                created: AtomicU64::new(min as u64),
+                ..PoolCounters::default()
+            },
+            pool_opts,
+            hooks,
+            closed: AtomicBool::new(false),
+            _maintenance_stop: maintenance_stop,
+        });
+        if let (Some(interval), Some(stop)) = (maintenance_interval, maintenance_stop_rx) {
+            let weak = Arc::downgrade(&arced_pool);
+            thread::Builder::new()
+                .name("mysql-pool-maintenance".into())
+                .spawn(move || run_maintenance(weak, stop, interval))?;
+        }
         Ok(Pool {
-            arced_pool: Arc::new(ArcedPool {
-                inner: (Mutex::new(pool), Condvar::new()),
-                min,
-                max,
-                count: AtomicUsize::new(min),
-            }),
+            arced_pool,
             use_cache: true,
             check_health: true,
         })
@@ -237,6 +664,99 @@ impl Pool {
         self._get_conn(None::<String>, Some(timeout_ms), true)
     }
 
+    /// Closes the pool without waiting for connections in use (see [`Pool::drain`]).
+    pub fn close(&self) {
+        // the pool is closed even if `drain` returns an error
+        let _ = self.drain(Duration::from_secs(0));
+    }
+
+    /// Gracefully closes the pool.
+    ///
+    /// *   new and currently blocked `get_conn` calls (on this pool and all its clones)
+    ///     will fail with `DriverError::PoolClosed`;
+    /// *   idle connections are disconnected (`COM_QUIT` is sent to the server);
+    /// *   connections in use are disconnected as soon as they are returned to the pool.
+    ///
+    /// Waits up to `timeout` for connections in use to be returned. Returns
+    /// `DriverError::Timeout` if some of them are still in use after the timeout.
+    pub fn drain(&self, timeout: Duration) -> Result<()> {
+        let deadline = Instant::now() + timeout;
+        let &(ref inner_pool, ref condvar) = &self.arced_pool.inner;
+
+        self.arced_pool.closed.store(true, Ordering::SeqCst);
+        inner_pool.lock()?.notify_all_waiters();
+
+        loop {
+            let idle = mem::take(&mut inner_pool.lock()?.pool);
+            for idle in idle {
+                self.arced_pool.conn_closed();
+                // `Conn` sends `COM_QUIT` on drop
+                drop(idle);
+            }
+
+            let pool = inner_pool.lock()?;
+            if pool.pool.is_empty() && self.arced_pool.count.load(Ordering::SeqCst) == 0 {
+                return Ok(());
+            }
+            let now = Instant::now();
+            if now >= deadline {
+                return Err(DriverError::Timeout.into());
+            }
+            // connections returned concurrently with the `drain` call might end up in the pool,
+            // so it is checked periodically
+            let wait = cmp::min(deadline - now, Duration::from_millis(50));
+            drop(condvar.wait_timeout(pool, wait)?);
+        }
+    }
+
+    /// Returns `true` if the pool was closed (see [`Pool::drain`]).
+    pub fn is_closed(&self) -> bool {
+        self.arced_pool.closed.load(Ordering::SeqCst)
+    }
+
+    /// Returns a snapshot of the pool state and statistics.
+    ///
+    /// ```rust
+    /// # mysql::doctest_wrapper!(__result, {
+    /// # use mysql::*;
+    /// let pool = Pool::new_manual(1, 10, get_opts())?;
+    /// let conn = pool.get_conn()?;
+    ///
+    /// let stats = pool.stats();
+    /// assert_eq!(stats.idle(), 0);
+    /// assert_eq!(stats.in_use(), 1);
+    /// # drop(conn);
+    /// # });
+    /// ```
+    pub fn stats(&self) -> PoolStats {
+        let (idle, waiters) = self
+            .arced_pool
+            .inner
+            .0
+            .lock()
+            .map(|pool| (pool.pool.len(), pool.waiters.len()))
+            .unwrap_or_else(|poisoned| {
+                let pool = poisoned.into_inner();
+                (pool.pool.len(), pool.waiters.len())
+            });
+        let count = self.arced_pool.count.load(Ordering::SeqCst);
+        let counters = &self.arced_pool.counters;
+        let mut checkout_wait = [0; CHECKOUT_WAIT_BUCKETS.len() + 1];
+        for (bucket, counter) in checkout_wait.iter_mut().zip(&counters.checkout_wait) {
+            *bucket = counter.load(Ordering::Relaxed);
+        }
+        PoolStats {
+            idle,
+            in_use: count.saturating_sub(idle),
+            created: counters.created.load(Ordering::Relaxed),
+            closed: counters.closed.load(Ordering::Relaxed),
+            waiters,
+            checkout_wait,
+            timeouts: counters.timeouts.load(Ordering::Relaxed),
+            failed_health_checks: counters.failed_health_checks.load(Ordering::Relaxed),
+        }
+    }
+
     /// Shortcut for `pool.get_conn()?.start_transaction(..)`.
     pub fn start_transaction(&self, tx_opts: TxOpts) -> Result<Transaction<'static>> {
         let conn = self._get_conn(None::<String>, None, false)?;
@@ -250,6 +770,48 @@ impl Pool {
             Err(e) => Err(e),
         }
     }
+
+    /// Runs `f` within a transaction on a pooled connection (see [`Conn::transaction`]).
+    ///
+    /// In addition to retryable errors, a transaction, that fails with a connectivity error
+    /// before commit, is retried on a fresh connection. An error on commit itself is never
+    /// retried unless it is retryable according to the `retry_policy`, because it is unknown
+    /// whether the transaction was committed.
+    pub fn transaction<T, F>(
+        &self,
+        tx_opts: TxOpts,
+        retry_policy: RetryPolicy,
+        mut f: F,
+    ) -> Result<T>
+    where
+        F: FnMut(&mut Transaction<'_>) -> Result<T>,
+    {
+        let mut retries = 0;
+        let mut call_ping = false;
+        loop {
+            let mut conn = self._get_conn(None::<String>, None, call_ping)?;
+            match conn.as_mut().transaction_attempt(tx_opts, &mut f) {
+                Ok(value) => return Ok(value),
+                Err((err, _))
+                    if retries < retry_policy.max_retries() && retry_policy.is_retryable(&err) =>
+                {
+                    drop(conn);
+                    thread::sleep(retry_policy.backoff(retries));
+                    call_ping = false;
+                }
+                Err((err, false))
+                    if retries < retry_policy.max_retries() && err.is_connectivity_error() =>
+                {
+                    // broken connection is closed rather than returned to the pool,
+                    // other idle connections are checked before the retry
+                    drop(conn.unwrap());
+                    call_ping = true;
+                }
+                Err((err, _)) => return Err(err),
+            }
+            retries += 1;
+        }
+    }
 }
 
 impl fmt::Debug for Pool {
@@ -312,16 +874,35 @@ impl Deref for PooledConn {
 
 impl Drop for PooledConn {
     fn drop(&mut self) {
-        if self.pool.arced_pool.count.load(Ordering::Relaxed) > self.pool.arced_pool.max
+        if self.pool.is_closed() {
+            // `Conn` sends `COM_QUIT` on drop
+            drop(self.conn.take());
+            self.pool.arced_pool.conn_closed();
+            (self.pool.arced_pool.inner).1.notify_all();
+        } else if self.pool.arced_pool.count.load(Ordering::Relaxed) > self.pool.arced_pool.max
             || self.conn.is_none()
         {
-            self.pool.arced_pool.count.fetch_sub(1, Ordering::SeqCst);
+            self.pool.arced_pool.conn_closed();
+            self.pool.arced_pool.notify_waiter();
         } else {
-            self.conn.as_mut().unwrap().set_local_infile_handler(None);
+            let mut conn = self.conn.take().unwrap();
+            conn.set_local_infile_handler(None);
+            conn.set_progress_handler(None);
+            let is_clean = self.pool.arced_pool.cleanup(&mut conn).is_ok()
+                && match self.pool.arced_pool.hooks {
+                    Some(ref hooks) => hooks.after_release(&mut conn),
+                    None => true,
+                };
             let mut pool = (self.pool.arced_pool.inner).0.lock().unwrap();
-            pool.pool.push_back(self.conn.take().unwrap());
-            drop(pool);
-            (self.pool.arced_pool.inner).1.notify_one();
+            if !is_clean || pool.is_expired(&conn) {
+                drop(pool);
+                self.pool.arced_pool.conn_closed();
+                drop(conn);
+                self.pool.arced_pool.notify_waiter();
+            } else {
+                pool.pool.push_back(IdleConn::new(conn));
+                pool.notify_waiter();
+            }
         }
     }
 }
@@ -341,6 +922,42 @@ impl PooledConn {
         self.conn.take().unwrap().get_binlog_stream(request)
     }
 
+    /// Redirects to [`Conn::exec_cursor`].
+    pub fn exec_cursor<S, P>(&mut self, stmt: S, params: P, fetch_size: u32) -> Result<Cursor<'_>>
+    where
+        S: AsStatement,
+        P: Into<Params>,
+    {
+        self.as_mut().exec_cursor(stmt, params, fetch_size)
+    }
+
+    /// Redirects to [`Conn::with_query_attributes`].
+    pub fn with_query_attributes<N, V>(&mut self, attributes: &[(N, V)]) -> WithQueryAttributes<'_>
+    where
+        N: AsRef<str>,
+        V: Into<Value> + Clone,
+    {
+        self.as_mut().with_query_attributes(attributes)
+    }
+
+    /// Redirects to [`Conn::wait_for_gtid_set`].
+    pub fn wait_for_gtid_set<T: AsRef<str>>(
+        &mut self,
+        gtid_set: T,
+        timeout: Option<Duration>,
+    ) -> Result<GtidWaitResult> {
+        self.as_mut().wait_for_gtid_set(gtid_set, timeout)
+    }
+
+    /// Redirects to [`Conn::with_deadline`].
+    #[cfg(not(target_os = "wasi"))]
+    pub fn with_deadline<T, F>(&mut self, deadline: Instant, f: F) -> Result<T>
+    where
+        F: FnOnce(&mut Conn) -> Result<T>,
+    {
+        self.as_mut().with_deadline(deadline, f)
+    }
+
     /// Gives mutable reference to the wrapped
     /// [`Conn`](struct.Conn.html).
     pub fn as_mut(&mut self) -> &mut Conn {
@@ -372,6 +989,13 @@ impl PooledConn {
             .unwrap()
             .set_local_infile_handler(handler);
     }
+
+    /// A way to override default progress handler for this pooled connection. Destructor will
+    /// restore original handler before returning connection to a pool.
+    /// See [`Conn::set_progress_handler`](struct.Conn.html#method.set_progress_handler).
+    pub fn set_progress_handler(&mut self, handler: Option<ProgressHandler>) {
+        self.conn.as_mut().unwrap().set_progress_handler(handler);
+    }
 }
 
 impl Queryable for PooledConn {
@@ -401,11 +1025,11 @@ impl Queryable for PooledConn {
 #[allow(non_snake_case)]
 mod test {
     mod pool {
-        use std::{thread, time::Duration};
+        use std::{sync::atomic::Ordering, thread, time::Duration};
 
         use crate::{
             from_value, prelude::*, test_misc::get_opts, DriverError, Error, OptsBuilder, Pool,
-            TxOpts,
+            PoolOpts, RetryPolicy, TxOpts,
         };
 
         #[test]
@@ -513,6 +1137,57 @@ mod test {
             pool.start_transaction(TxOpts::default()).unwrap();
         }
         #[test]
+        fn should_run_init_after_fixing_connectivity_errors() {
+            let opts = OptsBuilder::from_opts(get_opts()).init(vec!["SET @a = 42"]);
+            let pool = Pool::new_manual(1, 1, opts).unwrap();
+
+            let id: u32 = pool
+                .get_conn()
+                .unwrap()
+                .exec_first("SELECT CONNECTION_ID();", ())
+                .unwrap()
+                .unwrap();
+
+            let mut killer = crate::Conn::new(get_opts()).unwrap();
+            killer.query_drop(format!("KILL {}", id)).unwrap();
+            thread::sleep(Duration::from_millis(250));
+
+            let a: Option<u8> = pool
+                .get_conn()
+                .unwrap()
+                .query_first("SELECT @a")
+                .unwrap()
+                .unwrap();
+            assert_eq!(a, Some(42));
+        }
+        #[test]
+        fn should_retry_transaction_on_a_fresh_connection() {
+            let pool = Pool::new_manual(1, 1, get_opts()).unwrap();
+
+            let mut attempts = 0;
+            let id = pool
+                .transaction(TxOpts::default(), RetryPolicy::default(), |tx| {
+                    attempts += 1;
+                    let id = tx.exec_first::<u32, _, _>("SELECT CONNECTION_ID()", ())?;
+                    if attempts == 1 {
+                        // connection dies before commit
+                        let mut killer = crate::Conn::new(get_opts())?;
+                        killer.query_drop(format!("KILL {}", id.unwrap()))?;
+                        thread::sleep(Duration::from_millis(250));
+                        tx.query_drop("SELECT 1")?;
+                    }
+                    Ok(id)
+                })
+                .unwrap();
+            assert_eq!(attempts, 2);
+            assert!(id.is_some());
+
+            // broken connection was closed instead of being returned to the pool
+            let stats = pool.stats();
+            assert_eq!((stats.total_created(), stats.total_closed()), (2, 1));
+            assert_eq!(stats.failed_health_checks(), 0);
+        }
+        #[test]
         fn should_execute_queryes_on_PooledConn() {
             let pool = Pool::new(get_opts()).unwrap();
             let mut threads = Vec::new();
@@ -649,6 +1324,412 @@ mod test {
             Ok(())
         }
 
+        #[test]
+        fn should_close_connections_past_max_lifetime() -> crate::Result<()> {
+            let pool_opts = PoolOpts::default().with_max_lifetime(Some(Duration::from_millis(500)));
+            let opts = OptsBuilder::from_opts(get_opts()).pool_opts(pool_opts);
+            let pool = Pool::new_manual(1, 1, opts)?;
+
+            let connection_id = pool.get_conn()?.connection_id();
+            assert_eq!(pool.get_conn()?.connection_id(), connection_id);
+
+            // expired on return
+            let conn = pool.get_conn()?;
+            thread::sleep(Duration::from_millis(600));
+            drop(conn);
+            assert_eq!(pool.arced_pool.count.load(Ordering::SeqCst), 0);
+
+            let connection_id = pool.get_conn()?.connection_id();
+            assert_eq!(pool.arced_pool.count.load(Ordering::SeqCst), 1);
+
+            // expired on checkout
+            thread::sleep(Duration::from_millis(600));
+            assert_ne!(pool.get_conn()?.connection_id(), connection_id);
+            assert_eq!(pool.arced_pool.count.load(Ordering::SeqCst), 1);
+
+            Ok(())
+        }
+
+        #[test]
+        fn should_close_connections_past_idle_timeout() -> crate::Result<()> {
+            let pool_opts = PoolOpts::default().with_idle_timeout(Some(Duration::from_millis(500)));
+            let opts = OptsBuilder::from_opts(get_opts()).pool_opts(pool_opts);
+            let pool = Pool::new_manual(1, 1, opts)?;
+
+            // connection in use is not idle
+            let conn = pool.get_conn()?;
+            let connection_id = conn.connection_id();
+            thread::sleep(Duration::from_millis(600));
+            drop(conn);
+            assert_eq!(pool.get_conn()?.connection_id(), connection_id);
+
+            thread::sleep(Duration::from_millis(600));
+            assert_ne!(pool.get_conn()?.connection_id(), connection_id);
+            assert_eq!(pool.arced_pool.count.load(Ordering::SeqCst), 1);
+
+            Ok(())
+        }
+
+        #[test]
+        fn should_report_stats() -> crate::Result<()> {
+            let pool = Pool::new_manual(1, 2, get_opts())?;
+            let stats = pool.stats();
+            assert_eq!((stats.idle(), stats.in_use()), (1, 0));
+            assert_eq!((stats.total_created(), stats.total_closed()), (1, 0));
+
+            let conn1 = pool.get_conn()?;
+            let conn2 = pool.get_conn()?;
+            let stats = pool.stats();
+            assert_eq!((stats.idle(), stats.in_use()), (0, 2));
+            assert_eq!(stats.total_created(), 2);
+
+            match pool.try_get_conn(100) {
+                Err(Error::DriverError(DriverError::Timeout)) => (),
+                _ => panic!("Timeout error expected"),
+            }
+            let stats = pool.stats();
+            assert_eq!(stats.timeouts(), 1);
+            assert_eq!(stats.waiters(), 0);
+            assert_eq!(
+                stats
+                    .checkout_wait_histogram()
+                    .into_iter()
+                    .map(|(_, count)| count)
+                    .sum::<u64>(),
+                2
+            );
+
+            let waiter = thread::spawn({
+                let pool = pool.clone();
+                move || pool.get_conn().map(drop)
+            });
+            while pool.stats().waiters() == 0 {
+                thread::sleep(Duration::from_millis(10));
+            }
+            drop(conn1);
+            waiter.join().unwrap()?;
+            assert_eq!(pool.stats().waiters(), 0);
+
+            conn2.unwrap();
+            let stats = pool.stats();
+            assert_eq!((stats.idle(), stats.in_use()), (1, 0));
+            assert_eq!(stats.total_closed(), 1);
+
+            Ok(())
+        }
+
+        #[test]
+        fn should_maintain_pool_in_background() -> crate::Result<()> {
+            let interval = Duration::from_millis(200);
+            let pool_opts = PoolOpts::default().with_maintenance_interval(Some(interval));
+            let opts = OptsBuilder::from_opts(get_opts()).pool_opts(pool_opts);
+            let pool = Pool::new_manual(2, 10, opts)?;
+
+            // trims down to `min`
+            let conns = (0..4)
+                .map(|_| pool.get_conn())
+                .collect::<Result<Vec<_>, _>>()?;
+            drop(conns);
+            assert_eq!(pool.stats().idle(), 4);
+            thread::sleep(interval * 3);
+            assert_eq!(pool.stats().idle(), 2);
+
+            // replaces broken connections
+            let ids = {
+                let conns = (0..2)
+                    .map(|_| pool.get_conn())
+                    .collect::<Result<Vec<_>, _>>()?;
+                conns
+                    .iter()
+                    .map(|conn| conn.connection_id())
+                    .collect::<Vec<_>>()
+            };
+            let mut conn = crate::Conn::new(get_opts())?;
+            for id in &ids {
+                conn.query_drop(format!("KILL {}", id))?;
+            }
+            thread::sleep(interval * 3);
+            let stats = pool.stats();
+            assert_eq!(stats.failed_health_checks(), 2);
+            assert_eq!((stats.idle(), stats.in_use()), (2, 0));
+
+            // stops when the last clone is dropped
+            let weak = std::sync::Arc::downgrade(&pool.arced_pool);
+            drop(pool);
+            thread::sleep(interval * 2);
+            assert!(weak.upgrade().is_none());
+
+            Ok(())
+        }
+
+        #[test]
+        fn should_reset_connection_on_return() -> crate::Result<()> {
+            let pool_opts = PoolOpts::default().with_reset_connection(true);
+            let opts = OptsBuilder::from_opts(get_opts())
+                .init(vec!["SET @init = 42"])
+                .pool_opts(pool_opts);
+            let pool = Pool::new_manual(1, 1, opts)?;
+
+            let mut conn = pool.get_conn()?;
+            conn.query_drop("SET @foo = 'bar'")?;
+            conn.query_drop("CREATE TEMPORARY TABLE mysql.tbl(a INT)")?;
+            drop(conn);
+
+            let mut conn = pool.get_conn()?;
+            assert_eq!(
+                conn.query_first::<Option<String>, _>("SELECT @foo")?,
+                Some(None)
+            );
+            assert_eq!(conn.query_first::<u8, _>("SELECT @init")?, Some(42));
+            conn.query_drop("CREATE TEMPORARY TABLE mysql.tbl(a INT)")?;
+
+            Ok(())
+        }
+
+        #[test]
+        fn should_discard_connection_if_cleanup_fails() -> crate::Result<()> {
+            use crate::CleanupHandler;
+
+            let handler = CleanupHandler::new(|conn| {
+                let dirty: Option<u8> = conn.query_first("SELECT @dirty")?;
+                match dirty {
+                    Some(1) => Err(DriverError::SetupError.into()),
+                    _ => Ok(()),
+                }
+            });
+            let pool_opts = PoolOpts::default().with_cleanup_handler(Some(handler));
+            let opts = OptsBuilder::from_opts(get_opts()).pool_opts(pool_opts);
+            let pool = Pool::new_manual(1, 1, opts)?;
+
+            let conn = pool.get_conn()?;
+            let connection_id = conn.connection_id();
+            drop(conn);
+            let mut conn = pool.get_conn()?;
+            assert_eq!(conn.connection_id(), connection_id);
+
+            conn.query_drop("SET @dirty = 1")?;
+            drop(conn);
+            let stats = pool.stats();
+            assert_eq!((stats.idle(), stats.in_use()), (0, 0));
+            assert_eq!(stats.total_closed(), 1);
+            assert_ne!(pool.get_conn()?.connection_id(), connection_id);
+
+            Ok(())
+        }
+
+        #[test]
+        fn should_drain_pool() -> crate::Result<()> {
+            let pool = Pool::new_manual(1, 1, get_opts())?;
+            let conn = pool.get_conn()?;
+
+            let waiter = thread::spawn({
+                let pool = pool.clone();
+                move || pool.get_conn().map(drop)
+            });
+            while pool.stats().waiters() == 0 {
+                thread::sleep(Duration::from_millis(10));
+            }
+
+            let drain = thread::spawn({
+                let pool = pool.clone();
+                move || pool.drain(Duration::from_secs(10))
+            });
+
+            match waiter.join().unwrap() {
+                Err(Error::DriverError(DriverError::PoolClosed)) => (),
+                _ => panic!("PoolClosed error expected"),
+            }
+            match pool.get_conn() {
+                Err(Error::DriverError(DriverError::PoolClosed)) => (),
+                _ => panic!("PoolClosed error expected"),
+            }
+            assert!(pool.is_closed());
+
+            drop(conn);
+            drain.join().unwrap()?;
+            let stats = pool.stats();
+            assert_eq!((stats.idle(), stats.in_use()), (0, 0));
+            assert_eq!(stats.total_closed(), 1);
+
+            Ok(())
+        }
+
+        #[test]
+        fn should_timeout_on_drain() -> crate::Result<()> {
+            let pool = Pool::new_manual(2, 2, get_opts())?;
+            let conn = pool.get_conn()?;
+
+            match pool.drain(Duration::from_millis(100)) {
+                Err(Error::DriverError(DriverError::Timeout)) => (),
+                _ => panic!("Timeout error expected"),
+            }
+            let stats = pool.stats();
+            assert_eq!((stats.idle(), stats.in_use()), (0, 1));
+
+            drop(conn);
+            assert_eq!(pool.stats().in_use(), 0);
+            pool.drain(Duration::from_millis(100))?;
+
+            Ok(())
+        }
+
+        #[test]
+        fn should_call_lifecycle_hooks() -> crate::Result<()> {
+            use crate::{Conn, ConnHooks};
+            use std::sync::{atomic::AtomicUsize, Arc};
+
+            #[derive(Debug, Default)]
+            struct Calls {
+                connected: AtomicUsize,
+                acquired: AtomicUsize,
+                released: AtomicUsize,
+                closed: AtomicUsize,
+            }
+
+            #[derive(Debug)]
+            struct Hooks(Arc<Calls>);
+
+            impl ConnHooks for Hooks {
+                fn after_connect(&self, conn: &mut Conn) -> crate::Result<()> {
+                    self.0.connected.fetch_add(1, Ordering::SeqCst);
+                    conn.query_drop("SET @tag = 'pooled'")
+                }
+
+                fn before_acquire(&self, conn: &mut Conn) -> bool {
+                    self.0.acquired.fetch_add(1, Ordering::SeqCst);
+                    let tag: Option<String> = conn.query_first("SELECT @tag").unwrap();
+                    tag.as_deref() == Some("pooled")
+                }
+
+                fn after_release(&self, conn: &mut Conn) -> bool {
+                    self.0.released.fetch_add(1, Ordering::SeqCst);
+                    let veto: Option<Option<u8>> = conn.query_first("SELECT @veto").unwrap();
+                    veto != Some(Some(1))
+                }
+
+                fn on_close(&self, _conn: &mut Conn) {
+                    self.0.closed.fetch_add(1, Ordering::SeqCst);
+                }
+            }
+
+            let calls = Arc::new(Calls::default());
+            let counts = || {
+                (
+                    calls.connected.load(Ordering::SeqCst),
+                    calls.acquired.load(Ordering::SeqCst),
+                    calls.released.load(Ordering::SeqCst),
+                    calls.closed.load(Ordering::SeqCst),
+                )
+            };
+            let opts = OptsBuilder::from_opts(get_opts())
+                .prefer_socket(false)
+                .hooks(Hooks(calls.clone()));
+            let pool = Pool::new_manual(1, 1, opts)?;
+            assert_eq!(counts(), (1, 0, 0, 0));
+
+            let mut conn = pool.get_conn()?;
+            let connection_id = conn.connection_id();
+            conn.query_drop("SET @veto = 1")?;
+            drop(conn);
+            assert_eq!(counts(), (1, 1, 1, 1));
+
+            let conn = pool.get_conn()?;
+            assert_ne!(conn.connection_id(), connection_id);
+            drop(conn);
+            assert_eq!(counts(), (2, 2, 2, 1));
+
+            drop(pool);
+            assert_eq!(counts(), (2, 2, 2, 2));
+
+            Ok(())
+        }
+
+        #[test]
+        fn should_give_up_on_vetoed_connections() -> crate::Result<()> {
+            use crate::{Conn, ConnHooks};
+
+            #[derive(Debug)]
+            struct VetoAll;
+
+            impl ConnHooks for VetoAll {
+                fn before_acquire(&self, _conn: &mut Conn) -> bool {
+                    false
+                }
+            }
+
+            let opts = OptsBuilder::from_opts(get_opts()).hooks(VetoAll);
+            let pool = Pool::new_manual(1, 1, opts)?;
+
+            match pool.get_conn() {
+                Err(Error::DriverError(DriverError::AcquireVetoed)) => (),
+                _ => panic!("AcquireVetoed error expected"),
+            }
+            let stats = pool.stats();
+            assert_eq!(stats.total_closed(), 10);
+            assert_eq!(stats.idle() + stats.in_use(), 0);
+
+            Ok(())
+        }
+
+        #[test]
+        fn should_serve_waiters_in_arrival_order() -> crate::Result<()> {
+            let pool = Pool::new_manual(1, 1, get_opts())?;
+            let conn = pool.get_conn()?;
+            let order = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+
+            let mut waiters = Vec::new();
+            for i in 0..5 {
+                waiters.push(thread::spawn({
+                    let pool = pool.clone();
+                    let order = order.clone();
+                    move || {
+                        let conn = pool.get_conn()?;
+                        order.lock().unwrap().push(i);
+                        drop(conn);
+                        crate::Result::Ok(())
+                    }
+                }));
+                while pool.stats().waiters() <= i {
+                    thread::sleep(Duration::from_millis(10));
+                }
+            }
+
+            drop(conn);
+            for waiter in waiters {
+                waiter.join().unwrap()?;
+            }
+            assert_eq!(*order.lock().unwrap(), vec![0, 1, 2, 3, 4]);
+
+            Ok(())
+        }
+
+        #[test]
+        fn should_fail_fast_if_waiters_queue_is_full() -> crate::Result<()> {
+            let opts = OptsBuilder::from_opts(get_opts())
+                .pool_opts(PoolOpts::default().with_max_waiters(Some(1)));
+            let pool = Pool::new_manual(1, 1, opts)?;
+            let conn = pool.get_conn()?;
+
+            let waiter = thread::spawn({
+                let pool = pool.clone();
+                move || pool.get_conn().map(drop)
+            });
+            while pool.stats().waiters() == 0 {
+                thread::sleep(Duration::from_millis(10));
+            }
+
+            match pool.try_get_conn(10_000) {
+                Err(Error::DriverError(DriverError::PoolQueueFull)) => (),
+                _ => panic!("PoolQueueFull error expected"),
+            }
+
+            drop(conn);
+            waiter.join().unwrap()?;
+            assert_eq!(pool.stats().waiters(), 0);
+
+            Ok(())
+        }
+
         #[test]
         fn should_start_transaction_on_PooledConn() {
             let pool = Pool::new(get_opts()).unwrap();
@@ -0,0 +1,79 @@
+// Copyright (c) 2021 Anatoly Ikorsky
+//
+// Licensed under the Apache License, Version 2.0
+// <LICENSE-APACHE or http://www.apache.org/licenses/LICENSE-2.0> or the MIT
+// license <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. All files in the project carrying such notice may not be copied,
+// modified, or distributed except according to those terms.
+
+use mysql_common::packets::ProgressReport;
+
+use std::{
+    fmt,
+    sync::{Arc, Mutex},
+};
+
+pub(crate) type ProgressHandlerInner = Arc<Mutex<dyn for<'a> FnMut(&'a ProgressReport<'_>) + Send>>;
+
+/// Callback to handle progress reports of long running commands.
+///
+/// Progress reports are a MariaDB feature. The server will send them for commands
+/// such as `ALTER TABLE`, `LOAD DATA` or `CREATE INDEX` if the statement runs longer
+/// than the `progress_report_time` server variable. The callback is passed the
+/// [`ProgressReport`] with the current stage, the max stage and the progress of
+/// the current stage (use [`ProgressReport::progress`] divided by `1000` to get
+/// the percentage).
+///
+/// Consult [MariaDB documentation](https://mariadb.com/kb/en/progress-reporting/)
+/// for details.
+///
+/// # Support
+///
+/// Note that MySql server does not support this functionality, so the callback
+/// will never be called.
+///
+/// ```rust
+/// # mysql::doctest_wrapper!(__result, {
+/// # use mysql::*;
+/// # use mysql::prelude::*;
+/// # let pool = Pool::new(get_opts())?;
+/// # let mut conn = pool.get_conn()?;
+/// conn.set_progress_handler(Some(ProgressHandler::new(|report| {
+///     println!(
+///         "stage {} of {}: {:.3}% ({})",
+///         report.stage(),
+///         report.max_stage(),
+///         report.progress() as f64 / 1000.0,
+///         report.stage_info_str(),
+///     );
+/// })));
+///
+/// conn.query_drop("CREATE TEMPORARY TABLE mysql.tbl(a INT)")?;
+/// conn.query_drop("ALTER TABLE mysql.tbl ADD COLUMN b INT")?;
+/// # });
+/// ```
+#[derive(Clone)]
+pub struct ProgressHandler(pub(crate) ProgressHandlerInner);
+
+impl ProgressHandler {
+    pub fn new<F>(f: F) -> Self
+    where
+        F: for<'a> FnMut(&'a ProgressReport<'_>) + Send + 'static,
+    {
+        ProgressHandler(Arc::new(Mutex::new(f)))
+    }
+}
+
+impl PartialEq for ProgressHandler {
+    fn eq(&self, other: &ProgressHandler) -> bool {
+        (&*self.0 as *const _) == (&*other.0 as *const _)
+    }
+}
+
+impl Eq for ProgressHandler {}
+
+impl fmt::Debug for ProgressHandler {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
+        write!(f, "ProgressHandler(...)")
+    }
+}
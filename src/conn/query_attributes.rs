@@ -0,0 +1,178 @@
+// Copyright (c) 2021 Anatoly Ikorsky
+//
+// Licensed under the Apache License, Version 2.0
+// <LICENSE-APACHE or http://www.apache.org/licenses/LICENSE-2.0> or the MIT
+// license <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. All files in the project carrying such notice may not be copied,
+// modified, or distributed except according to those terms.
+
+use bytes::BufMut;
+use mysql_common::{io::BufMutExt, proto::MySerialize};
+
+use std::ops::Deref;
+
+use crate::{
+    conn::query_result::{Binary, Text},
+    consts::{ColumnType, Command, CursorType},
+    prelude::*,
+    Conn, Params, QueryResult, Result, Statement, Value,
+};
+
+/// `COM_STMT_EXECUTE` flag, that signals that the parameter count is sent.
+const PARAMETER_COUNT_AVAILABLE: u8 = 0x08;
+
+/// Parameter type flag for unsigned integers.
+const UNSIGNED_FLAG: u8 = 0x80;
+
+/// Connection, that sends query attributes with every query
+/// (see [`Conn::with_query_attributes`]).
+///
+/// Attributes are cleared once this value is dropped.
+#[derive(Debug)]
+pub struct WithQueryAttributes<'a> {
+    conn: &'a mut Conn,
+}
+
+impl<'a> WithQueryAttributes<'a> {
+    pub(crate) fn new(conn: &'a mut Conn) -> Self {
+        WithQueryAttributes { conn }
+    }
+}
+
+impl Deref for WithQueryAttributes<'_> {
+    type Target = Conn;
+
+    fn deref(&self) -> &Conn {
+        self.conn
+    }
+}
+
+impl Queryable for WithQueryAttributes<'_> {
+    fn query_iter<T: AsRef<str>>(&mut self, query: T) -> Result<QueryResult<'_, '_, '_, Text>> {
+        self.conn.query_iter(query)
+    }
+
+    fn prep<T: AsRef<str>>(&mut self, query: T) -> Result<Statement> {
+        self.conn.prep(query)
+    }
+
+    fn close(&mut self, stmt: Statement) -> Result<()> {
+        self.conn.close(stmt)
+    }
+
+    fn exec_iter<S, P>(&mut self, stmt: S, params: P) -> Result<QueryResult<'_, '_, '_, Binary>>
+    where
+        S: AsStatement,
+        P: Into<Params>,
+    {
+        self.conn.exec_iter(stmt, params)
+    }
+}
+
+impl Drop for WithQueryAttributes<'_> {
+    fn drop(&mut self) {
+        self.conn.0.query_attributes.clear();
+    }
+}
+
+/// Returns binary protocol type and flags of the given value.
+fn param_type(value: &Value) -> [u8; 2] {
+    match value {
+        Value::NULL => [ColumnType::MYSQL_TYPE_NULL as u8, 0],
+        Value::Bytes(_) => [ColumnType::MYSQL_TYPE_VAR_STRING as u8, 0],
+        Value::Int(_) => [ColumnType::MYSQL_TYPE_LONGLONG as u8, 0],
+        Value::UInt(_) => [ColumnType::MYSQL_TYPE_LONGLONG as u8, UNSIGNED_FLAG],
+        Value::Float(_) => [ColumnType::MYSQL_TYPE_FLOAT as u8, 0],
+        Value::Double(_) => [ColumnType::MYSQL_TYPE_DOUBLE as u8, 0],
+        Value::Date(..) => [ColumnType::MYSQL_TYPE_DATETIME as u8, 0],
+        Value::Time(..) => [ColumnType::MYSQL_TYPE_TIME as u8, 0],
+    }
+}
+
+/// Writes the null bitmap, types, names and values of statement parameters
+/// followed by query attributes.
+///
+/// Values of `Value::Bytes` parameters are omitted if `as_long_data` is `true`.
+fn put_params(
+    buf: &mut Vec<u8>,
+    params: &[Value],
+    attributes: &[(String, Value)],
+    as_long_data: bool,
+) {
+    let values = || {
+        params
+            .iter()
+            .chain(attributes.iter().map(|(_, value)| value))
+    };
+
+    let mut bitmap = vec![0_u8; (params.len() + attributes.len() + 7) / 8];
+    for (i, value) in values().enumerate() {
+        if *value == Value::NULL {
+            bitmap[i / 8] |= 1 << (i % 8);
+        }
+    }
+    buf.put_slice(&bitmap);
+
+    // new params bound
+    buf.put_u8(1);
+    for value in params {
+        buf.put_slice(&param_type(value));
+        buf.put_lenenc_str(b"");
+    }
+    for (name, value) in attributes {
+        buf.put_slice(&param_type(value));
+        buf.put_lenenc_str(name.as_bytes());
+    }
+
+    for (i, value) in values().enumerate() {
+        match value {
+            Value::NULL => (),
+            Value::Bytes(_) if as_long_data && i < params.len() => (),
+            value => value.serialize(&mut *buf),
+        }
+    }
+}
+
+/// Writes query attributes of a `COM_QUERY` command (everything between the command byte
+/// and the query).
+///
+/// **Requires:** `CLIENT_QUERY_ATTRIBUTES` capability.
+pub(crate) fn put_query_attributes(buf: &mut Vec<u8>, attributes: &[(String, Value)]) {
+    buf.put_lenenc_int(attributes.len() as u64);
+    // parameter set count is always 1
+    buf.put_lenenc_int(1);
+    if !attributes.is_empty() {
+        put_params(buf, &[], attributes, false);
+    }
+}
+
+/// `COM_STMT_EXECUTE` request, that carries query attributes.
+///
+/// **Requires:** `CLIENT_QUERY_ATTRIBUTES` capability.
+pub(crate) struct ComStmtExecuteWithAttributes<'a> {
+    pub(crate) stmt_id: u32,
+    pub(crate) cursor_type: CursorType,
+    pub(crate) params: &'a [Value],
+    pub(crate) attributes: &'a [(String, Value)],
+    pub(crate) as_long_data: bool,
+}
+
+impl MySerialize for ComStmtExecuteWithAttributes<'_> {
+    fn serialize(&self, buf: &mut Vec<u8>) {
+        let mut flags = self.cursor_type.bits();
+        if !self.attributes.is_empty() {
+            flags |= PARAMETER_COUNT_AVAILABLE;
+        }
+
+        buf.put_u8(Command::COM_STMT_EXECUTE as u8);
+        buf.put_u32_le(self.stmt_id);
+        buf.put_u8(flags);
+        // iteration count is always 1
+        buf.put_u32_le(1);
+
+        if !self.params.is_empty() || !self.attributes.is_empty() {
+            buf.put_lenenc_int((self.params.len() + self.attributes.len()) as u64);
+            put_params(buf, self.params, self.attributes, self.as_long_data);
+        }
+    }
+}
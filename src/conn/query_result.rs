@@ -12,7 +12,7 @@ use mysql_common::packets::OkPacket;
 
 use std::{borrow::Cow, marker::PhantomData, sync::Arc};
 
-use crate::{conn::ConnMut, Column, Conn, Error, Result, Row};
+use crate::{conn::ConnMut, Column, Conn, Error, Result, Row, SessionState};
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Or<A, B> {
@@ -280,6 +280,13 @@ impl<'c, 't, 'tc, T: crate::prelude::Protocol> QueryResult<'c, 't, 'tc, T> {
             .unwrap_or_else(|| "".into())
     }
 
+    /// Session state of the connection (see [`crate::Conn::session_state`]).
+    ///
+    /// It reflects session state changes of every result set consumed so far.
+    pub fn session_state(&self) -> &SessionState {
+        self.conn.session_state()
+    }
+
     /// Returns columns of the current result rest.
     pub fn columns(&self) -> SetColumns {
         SetColumns {
@@ -0,0 +1,336 @@
+// Copyright (c) 2021 Anatoly Ikorsky
+//
+// Licensed under the Apache License, Version 2.0
+// <LICENSE-APACHE or http://www.apache.org/licenses/LICENSE-2.0> or the MIT
+// license <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. All files in the project carrying such notice may not be copied,
+// modified, or distributed except according to those terms.
+
+use std::{
+    fmt,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use crate::{prelude::*, Conn, DriverError, Error, Opts, Pool, PooledConn, Result, Row};
+
+/// Default value for [`ReplicatedPool::exclusion_period`].
+const DEFAULT_EXCLUSION_PERIOD: Duration = Duration::from_secs(30);
+
+/// Default value for [`ReplicatedPool::lag_check_interval`].
+const DEFAULT_LAG_CHECK_INTERVAL: Duration = Duration::from_secs(5);
+
+/// For how long a read waits for a busy replica before trying the next one.
+const REPLICA_CHECKOUT_TIMEOUT_MS: u32 = 100;
+
+#[derive(Debug, Default)]
+struct ReplicaState {
+    /// Replica won't be used until this moment.
+    excluded_until: Option<Instant>,
+    /// Last time the replication lag was checked.
+    lag_checked_at: Option<Instant>,
+}
+
+struct Replica {
+    pool: Pool,
+    state: Mutex<ReplicaState>,
+}
+
+impl Replica {
+    fn exclude(&self, until: Instant) {
+        if let Ok(mut state) = self.state.lock() {
+            state.excluded_until = Some(until);
+        }
+    }
+}
+
+/// Read/write splitting pool over a primary server and its replicas.
+///
+/// Write connections are taken from the primary pool. Read connections are balanced
+/// (round-robin) across healthy replicas. A replica is considered unhealthy and excluded
+/// for [`ReplicatedPool::exclusion_period`] if:
+///
+/// *   it is unreachable or fails the health check (see [`Pool::get_conn`]);
+/// *   its replication lag (`Seconds_Behind_Source`) exceeds
+///     [`ReplicatedPool::max_replication_lag`], or replication is stopped.
+///
+/// If there is no healthy replica, then read connection is taken from the primary pool
+/// (see [`ReplicatedPool::fallback_to_primary`]).
+///
+/// `ReplicatedPool` is cheap to clone, clones share pools and replica health state.
+///
+/// ```rust
+/// # mysql::doctest_wrapper!(__result, {
+/// # use mysql::*;
+/// # use mysql::prelude::*;
+/// # use std::time::Duration;
+/// # let primary_opts = get_opts();
+/// # let replica_opts = get_opts();
+/// let mut pool = ReplicatedPool::new(primary_opts, vec![replica_opts])?;
+/// pool.max_replication_lag(Some(Duration::from_secs(10)));
+///
+/// pool.get_write_conn()?.query_drop("CREATE TEMPORARY TABLE mysql.tbl(a INT)")?;
+/// let version: Option<String> = pool.get_read_conn()?.query_first("SELECT VERSION()")?;
+/// # });
+/// ```
+#[derive(Clone)]
+pub struct ReplicatedPool {
+    primary: Pool,
+    replicas: Arc<[Replica]>,
+    next_replica: Arc<AtomicUsize>,
+    max_replication_lag: Option<Duration>,
+    lag_check_interval: Duration,
+    exclusion_period: Duration,
+    fallback_to_primary: bool,
+}
+
+impl ReplicatedPool {
+    /// Creates new pool.
+    ///
+    /// The primary pool is created via [`Pool::new`]. Replica pools are created with `min = 0`
+    /// and `max = 100`, so they connect lazily and an unreachable replica is excluded
+    /// on the first read instead of failing the whole `ReplicatedPool`.
+    pub fn new<T, E, I>(primary: T, replicas: I) -> Result<ReplicatedPool>
+    where
+        Opts: TryFrom<T, Error = E>,
+        crate::Error: From<E>,
+        I: IntoIterator<Item = T>,
+    {
+        let primary = Pool::new(primary)?;
+        let replicas = replicas
+            .into_iter()
+            .map(|replica| Pool::new_manual(0, 100, replica))
+            .collect::<Result<Vec<_>>>()?;
+        Ok(ReplicatedPool::from_pools(primary, replicas))
+    }
+
+    /// Creates new pool from existing pools (use it to customize pool constraints and options).
+    pub fn from_pools(primary: Pool, replicas: Vec<Pool>) -> ReplicatedPool {
+        let replicas = replicas
+            .into_iter()
+            .map(|pool| Replica {
+                pool,
+                state: Mutex::new(ReplicaState::default()),
+            })
+            .collect::<Vec<_>>();
+        ReplicatedPool {
+            primary,
+            replicas: replicas.into(),
+            next_replica: Arc::new(AtomicUsize::new(0)),
+            max_replication_lag: None,
+            lag_check_interval: DEFAULT_LAG_CHECK_INTERVAL,
+            exclusion_period: DEFAULT_EXCLUSION_PERIOD,
+            fallback_to_primary: true,
+        }
+    }
+
+    /// Maximum acceptable replication lag (defaults to `None`, i.e. lag is not checked).
+    ///
+    /// Lag is checked via `SHOW REPLICA STATUS` (or `SHOW SLAVE STATUS` on older servers),
+    /// so the user needs the `REPLICATION CLIENT` privilege. A server that is not a replica
+    /// is considered to have no lag.
+    pub fn max_replication_lag(&mut self, max_replication_lag: Option<Duration>) {
+        self.max_replication_lag = max_replication_lag;
+    }
+
+    /// How often the replication lag is checked for every replica (defaults to 5 seconds).
+    pub fn lag_check_interval(&mut self, lag_check_interval: Duration) {
+        self.lag_check_interval = lag_check_interval;
+    }
+
+    /// For how long an unhealthy replica is excluded (defaults to 30 seconds).
+    pub fn exclusion_period(&mut self, exclusion_period: Duration) {
+        self.exclusion_period = exclusion_period;
+    }
+
+    /// Whether to use the primary for reads if there is no healthy replica (defaults to `true`).
+    ///
+    /// If `false`, then [`ReplicatedPool::get_read_conn`] will return
+    /// `DriverError::NoHealthyReplicas` instead.
+    pub fn fallback_to_primary(&mut self, fallback_to_primary: bool) {
+        self.fallback_to_primary = fallback_to_primary;
+    }
+
+    /// Pool of the primary server.
+    pub fn primary(&self) -> &Pool {
+        &self.primary
+    }
+
+    /// Pools of replicas.
+    pub fn replicas(&self) -> impl Iterator<Item = &Pool> {
+        self.replicas.iter().map(|replica| &replica.pool)
+    }
+
+    /// Gives you a connection to the primary server.
+    pub fn get_write_conn(&self) -> Result<PooledConn> {
+        self.primary.get_conn()
+    }
+
+    /// Gives you a connection to a healthy replica.
+    pub fn get_read_conn(&self) -> Result<PooledConn> {
+        let len = self.replicas.len();
+        let start = self.next_replica.fetch_add(1, Ordering::Relaxed);
+        for i in 0..len {
+            let replica = &self.replicas[(start + i) % len];
+            if let Some(conn) = self.try_replica(replica) {
+                return Ok(conn);
+            }
+        }
+
+        if self.fallback_to_primary || len == 0 {
+            self.primary.get_conn()
+        } else {
+            Err(DriverError::NoHealthyReplicas.into())
+        }
+    }
+
+    /// Returns a connection to the given replica if it is healthy.
+    fn try_replica(&self, replica: &Replica) -> Option<PooledConn> {
+        let now = Instant::now();
+        let check_lag = {
+            let state = replica.state.lock().ok()?;
+            if state
+                .excluded_until
+                .map(|until| now < until)
+                .unwrap_or(false)
+            {
+                return None;
+            }
+            self.max_replication_lag.is_some()
+                && state
+                    .lag_checked_at
+                    .map(|checked_at| now.duration_since(checked_at) >= self.lag_check_interval)
+                    .unwrap_or(true)
+        };
+
+        let mut conn = match replica.pool.try_get_conn(REPLICA_CHECKOUT_TIMEOUT_MS) {
+            Ok(conn) => conn,
+            // busy replica is skipped, but not excluded
+            Err(Error::DriverError(DriverError::Timeout)) => return None,
+            Err(_) => {
+                replica.exclude(now + self.exclusion_period);
+                return None;
+            }
+        };
+
+        if let (true, Some(max_lag)) = (check_lag, self.max_replication_lag) {
+            let is_healthy = match replication_lag(conn.as_mut()) {
+                // not a replica
+                Ok(None) => true,
+                Ok(Some(Some(lag))) => Duration::from_secs(lag) <= max_lag,
+                // replication is stopped or the status is unavailable
+                Ok(Some(None)) | Err(_) => false,
+            };
+            if let Ok(mut state) = replica.state.lock() {
+                state.lag_checked_at = Some(now);
+            }
+            if !is_healthy {
+                replica.exclude(now + self.exclusion_period);
+                return None;
+            }
+        }
+
+        Some(conn)
+    }
+}
+
+/// Returns replication lag in seconds.
+///
+/// Returns `None` if the server is not a replica and `Some(None)` if replication is stopped.
+fn replication_lag(conn: &mut Conn) -> Result<Option<Option<u64>>> {
+    let status: Option<Row> = match conn.query_first("SHOW REPLICA STATUS") {
+        // `SHOW REPLICA STATUS` is not supported prior to MySql 8.0.22 and MariaDB 10.5.1
+        Err(Error::MySqlError(_)) => conn.query_first("SHOW SLAVE STATUS")?,
+        result => result?,
+    };
+    Ok(status.map(|row| {
+        row.get::<Option<u64>, _>("Seconds_Behind_Source")
+            .or_else(|| row.get("Seconds_Behind_Master"))
+            .flatten()
+    }))
+}
+
+impl fmt::Debug for ReplicatedPool {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ReplicatedPool")
+            .field("primary", &self.primary)
+            .field(
+                "replicas",
+                &self.replicas.iter().map(|r| &r.pool).collect::<Vec<_>>(),
+            )
+            .field("max_replication_lag", &self.max_replication_lag)
+            .field("lag_check_interval", &self.lag_check_interval)
+            .field("exclusion_period", &self.exclusion_period)
+            .field("fallback_to_primary", &self.fallback_to_primary)
+            .finish()
+    }
+}
+
+#[cfg(not(target_os = "wasi"))]
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use crate::{
+        prelude::*, test_misc::get_opts, DriverError, Error, OptsBuilder, Pool, ReplicatedPool,
+    };
+
+    #[test]
+    fn should_split_reads_and_writes() -> crate::Result<()> {
+        let mut pool = ReplicatedPool::new(get_opts(), vec![get_opts(), get_opts()])?;
+        pool.max_replication_lag(Some(Duration::from_secs(60)));
+
+        let write_id = pool.get_write_conn()?.connection_id();
+        let read_id = pool.get_read_conn()?.connection_id();
+        assert_ne!(write_id, read_id);
+
+        let mut conn = pool.get_read_conn()?;
+        assert_eq!(conn.query_first::<u8, _>("SELECT 1")?, Some(1));
+
+        Ok(())
+    }
+
+    #[test]
+    fn should_exclude_unreachable_replica() -> crate::Result<()> {
+        let unreachable = OptsBuilder::from_opts(get_opts())
+            .ip_or_hostname(Some("127.0.0.1"))
+            .tcp_port(55555);
+        let mut pool = ReplicatedPool::new(OptsBuilder::from_opts(get_opts()), vec![unreachable])?;
+
+        // falls back to the primary
+        let conn = pool.get_read_conn()?;
+        drop(conn);
+
+        pool.fallback_to_primary(false);
+        match pool.get_read_conn() {
+            Err(Error::DriverError(DriverError::NoHealthyReplicas)) => (),
+            _ => panic!("NoHealthyReplicas error expected"),
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn should_skip_busy_replica() -> crate::Result<()> {
+        let primary = Pool::new_manual(1, 1, get_opts())?;
+        let replica = Pool::new_manual(1, 1, get_opts())?;
+        let pool = ReplicatedPool::from_pools(primary, vec![replica]);
+
+        let busy = pool.get_read_conn()?;
+        let replica_id = busy.connection_id();
+
+        // the only replica connection is in use, so the primary is used
+        let conn = pool.get_read_conn()?;
+        assert_ne!(conn.connection_id(), replica_id);
+        drop(conn);
+        drop(busy);
+
+        // busy replica wasn't excluded
+        assert_eq!(pool.get_read_conn()?.connection_id(), replica_id);
+
+        Ok(())
+    }
+}
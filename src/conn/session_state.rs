@@ -0,0 +1,247 @@
+// Copyright (c) 2021 Anatoly Ikorsky
+//
+// Licensed under the Apache License, Version 2.0
+// <LICENSE-APACHE or http://www.apache.org/licenses/LICENSE-2.0> or the MIT
+// license <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. All files in the project carrying such notice may not be copied,
+// modified, or distributed except according to those terms.
+
+use mysql_common::{
+    io::{ParseBuf, ReadMysqlExt},
+    packets::{session_state_change::SessionStateChange, SessionStateInfo},
+};
+
+use std::collections::HashMap;
+
+/// Session state trackers with the first MySql and MariaDB versions that support them.
+///
+/// MariaDB has no `session_track_gtids`, so GTIDs are taken from the `last_gtid`
+/// system variable there (tracked via `session_track_system_variables`).
+const TRACKERS: &[(&str, (u16, u16, u16), Option<(u16, u16, u16)>)] = &[
+    ("session_track_schema = ON", (5, 7, 4), Some((10, 2, 2))),
+    (
+        "session_track_system_variables = '*'",
+        (5, 7, 4),
+        Some((10, 2, 2)),
+    ),
+    (
+        "session_track_state_change = ON",
+        (5, 7, 4),
+        Some((10, 2, 2)),
+    ),
+    (
+        "session_track_transaction_info = 'STATE'",
+        (5, 7, 8),
+        Some((10, 3, 1)),
+    ),
+    ("session_track_gtids = OWN_GTID", (5, 7, 6), None),
+];
+
+/// Statement, that enables every session state tracker supported by the server
+/// (see [`crate::OptsBuilder::track_session_state`]).
+///
+/// Returns `None` if the server supports none of them.
+pub(crate) fn track_session_state_query(
+    server_version: Option<(u16, u16, u16)>,
+    mariadb_server_version: Option<(u16, u16, u16)>,
+) -> Option<String> {
+    let trackers = TRACKERS
+        .iter()
+        .filter(
+            |(_, mysql, mariadb)| match (mariadb_server_version, server_version) {
+                (Some(version), _) => mariadb.map_or(false, |mariadb| version >= mariadb),
+                (None, Some(version)) => version >= *mysql,
+                (None, None) => false,
+            },
+        )
+        .map(|(tracker, _, _)| format!("@@SESSION.{}", tracker))
+        .collect::<Vec<_>>();
+    if trackers.is_empty() {
+        None
+    } else {
+        Some(format!("SET {}", trackers.join(", ")))
+    }
+}
+
+/// Session state accumulated from session state changes reported by the server
+/// (see [`crate::Conn::session_state`]).
+///
+/// Server reports only changes of tracked values, so consider enabling all the trackers
+/// via [`crate::OptsBuilder::track_session_state`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SessionState {
+    schema: Option<String>,
+    system_variables: HashMap<String, String>,
+    gtids: Option<String>,
+    transaction_state: Option<TransactionState>,
+    is_changed: bool,
+}
+
+impl SessionState {
+    pub(crate) fn new(schema: Option<String>) -> Self {
+        SessionState {
+            schema,
+            ..SessionState::default()
+        }
+    }
+
+    /// Current default schema (tracked by `session_track_schema`).
+    pub fn schema(&self) -> Option<&str> {
+        self.schema.as_deref()
+    }
+
+    /// Value of a changed system variable (tracked by `session_track_system_variables`).
+    pub fn system_variable(&self, name: &str) -> Option<&str> {
+        self.system_variables.get(name).map(String::as_str)
+    }
+
+    /// All the changed system variables (tracked by `session_track_system_variables`).
+    pub fn system_variables(&self) -> &HashMap<String, String> {
+        &self.system_variables
+    }
+
+    /// GTIDs of the last committed transaction (tracked by `session_track_gtids` on MySql
+    /// and by the `last_gtid` system variable on MariaDB).
+    pub fn last_gtids(&self) -> Option<&str> {
+        self.gtids.as_deref()
+    }
+
+    /// Last reported transaction state (tracked by `session_track_transaction_info`).
+    pub fn transaction_state(&self) -> Option<&TransactionState> {
+        self.transaction_state.as_ref()
+    }
+
+    /// `true` if session state was changed (tracked by `session_track_state_change`).
+    pub fn is_changed(&self) -> bool {
+        self.is_changed
+    }
+
+    /// Resets everything except the schema (session is reset by `COM_RESET_CONNECTION`).
+    pub(crate) fn reset(&mut self) {
+        *self = SessionState::new(self.schema.take());
+    }
+
+    /// Applies session state changes from an OK packet (raw session state info).
+    ///
+    /// Query itself has succeeded, so changes, that couldn't be decoded, are skipped.
+    pub(crate) fn update(&mut self, mut data: &[u8]) {
+        while let Some((entry, rest)) = split_entry(data) {
+            data = rest;
+            let change = ParseBuf(entry)
+                .parse::<SessionStateInfo<'_>>(())
+                .ok()
+                .and_then(|info| info.decode().ok().map(SessionStateChange::into_owned));
+            if let Some(change) = change {
+                self.apply(change);
+            }
+        }
+    }
+
+    fn apply(&mut self, change: SessionStateChange<'_>) {
+        match change {
+            SessionStateChange::Schema(schema) => {
+                self.schema = Some(schema.as_str().into_owned());
+            }
+            SessionStateChange::SystemVariables(vars) => {
+                for var in vars {
+                    let name = var.name_str().into_owned();
+                    let value = var.value_str().into_owned();
+                    // MariaDB reports GTIDs via the `last_gtid` system variable
+                    if name == "last_gtid" && !value.is_empty() {
+                        self.gtids = Some(value.clone());
+                    }
+                    self.system_variables.insert(name, value);
+                }
+            }
+            SessionStateChange::Gtids(gtids) => {
+                if let Some(gtids) = parse_gtids(gtids.as_bytes()) {
+                    self.gtids = Some(gtids);
+                }
+            }
+            SessionStateChange::TransactionState(state) => {
+                self.transaction_state = Some(TransactionState::new(state.as_bytes()));
+            }
+            SessionStateChange::IsTracked(is_changed) => {
+                self.is_changed = is_changed;
+            }
+            _ => (),
+        }
+    }
+}
+
+/// Splits the first session state change (type, length and data) off the given data.
+fn split_entry(data: &[u8]) -> Option<(&[u8], &[u8])> {
+    let mut rest = data.get(1..)?;
+    let len = rest.read_lenenc_int().ok()? as usize;
+    let entry_len = (data.len() - rest.len()).checked_add(len)?;
+    if entry_len <= data.len() {
+        Some(data.split_at(entry_len))
+    } else {
+        None
+    }
+}
+
+/// Parses `SESSION_TRACK_GTIDS` data (encoding specification followed by a GTID set).
+fn parse_gtids(data: &[u8]) -> Option<String> {
+    // the only defined encoding specification is `0` (string representation)
+    let mut data = match data.split_first() {
+        Some((0, rest)) => rest,
+        _ => return None,
+    };
+    let gtids = data.read_lenenc_str().ok()?;
+    Some(String::from_utf8_lossy(&gtids).into_owned())
+}
+
+/// Transaction state reported by the server (`session_track_transaction_info = 'STATE'`).
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct TransactionState(Vec<u8>);
+
+impl TransactionState {
+    fn new(state: &[u8]) -> Self {
+        TransactionState(state.to_vec())
+    }
+
+    fn has(&self, index: usize, flag: u8) -> bool {
+        self.0.get(index) == Some(&flag)
+    }
+
+    /// Raw state string (e.g. `T___W___`).
+    pub fn as_str(&self) -> &str {
+        std::str::from_utf8(&self.0).unwrap_or_default()
+    }
+
+    /// `true` if there is an active transaction (explicit or implicit).
+    pub fn is_active(&self) -> bool {
+        self.is_explicit() || self.has(0, b'I')
+    }
+
+    /// `true` if the active transaction was started explicitly (e.g. `START TRANSACTION`).
+    pub fn is_explicit(&self) -> bool {
+        self.has(0, b'T')
+    }
+
+    /// `true` if the transaction has read transactional or non-transactional tables.
+    pub fn has_reads(&self) -> bool {
+        self.has(1, b'r') || self.has(2, b'R')
+    }
+
+    /// `true` if the transaction has written transactional or non-transactional tables.
+    pub fn has_writes(&self) -> bool {
+        self.has(3, b'w') || self.has(4, b'W')
+    }
+
+    /// `true` if the transaction has executed statements unsafe for statement-based logging.
+    pub fn has_unsafe_statements(&self) -> bool {
+        self.has(5, b's')
+    }
+
+    /// `true` if the transaction has sent a result set to the client.
+    pub fn has_result_set(&self) -> bool {
+        self.has(6, b'S')
+    }
+
+    /// `true` if tables are locked via `LOCK TABLES`.
+    pub fn has_locked_tables(&self) -> bool {
+        self.has(7, b'L')
+    }
+}
@@ -8,7 +8,7 @@
 
 use mysql_common::packets::OkPacket;
 
-use std::{borrow::Cow, fmt};
+use std::{borrow::Cow, cmp, fmt, time::Duration};
 
 use crate::{
     conn::{
@@ -16,7 +16,8 @@ use crate::{
         ConnMut,
     },
     prelude::*,
-    LocalInfileHandler, Params, QueryResult, Result, Statement,
+    Conn, Cursor, Error, LocalInfileHandler, Params, QueryResult, Result, ServerError,
+    SessionState, Statement, Value, WithQueryAttributes,
 };
 
 /// MySql transaction options.
@@ -62,6 +63,84 @@ impl TxOpts {
     }
 }
 
+/// Retry policy of [`crate::Conn::transaction`] and [`crate::Pool::transaction`].
+///
+/// A transaction is retried if it fails with a deadlock (`ER_LOCK_DEADLOCK`) or a lock wait
+/// timeout (`ER_LOCK_WAIT_TIMEOUT`). Backoff between retries starts at
+/// [`RetryPolicy::initial_backoff`] and doubles on every retry
+/// up to [`RetryPolicy::max_backoff`].
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash)]
+pub struct RetryPolicy {
+    max_retries: usize,
+    initial_backoff: Duration,
+    max_backoff: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            max_retries: 3,
+            initial_backoff: Duration::from_millis(10),
+            max_backoff: Duration::from_secs(1),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Returns the maximum number of retries.
+    pub fn max_retries(&self) -> usize {
+        self.max_retries
+    }
+
+    /// Returns the backoff before the first retry.
+    pub fn initial_backoff(&self) -> Duration {
+        self.initial_backoff
+    }
+
+    /// Returns the maximum backoff between retries.
+    pub fn max_backoff(&self) -> Duration {
+        self.max_backoff
+    }
+
+    /// Defines the maximum number of retries (defaults to `3`, `0` disables retries).
+    pub fn set_max_retries(mut self, max_retries: usize) -> Self {
+        self.max_retries = max_retries;
+        self
+    }
+
+    /// Defines the backoff before the first retry (defaults to 10 milliseconds).
+    pub fn set_initial_backoff(mut self, initial_backoff: Duration) -> Self {
+        self.initial_backoff = initial_backoff;
+        self
+    }
+
+    /// Defines the maximum backoff between retries (defaults to 1 second).
+    pub fn set_max_backoff(mut self, max_backoff: Duration) -> Self {
+        self.max_backoff = max_backoff;
+        self
+    }
+
+    /// Returns `true` if a transaction, that failed with the given error, could be retried.
+    pub fn is_retryable(&self, err: &Error) -> bool {
+        match err {
+            Error::MySqlError(err) => {
+                err.code == ServerError::ER_LOCK_DEADLOCK as u16
+                    || err.code == ServerError::ER_LOCK_WAIT_TIMEOUT as u16
+            }
+            _ => false,
+        }
+    }
+
+    /// Returns the backoff before the given retry (starting from `0`).
+    pub(crate) fn backoff(&self, retry: usize) -> Duration {
+        let factor = 1_u32.checked_shl(retry as u32).unwrap_or(u32::MAX);
+        cmp::min(
+            self.initial_backoff.saturating_mul(factor),
+            self.max_backoff,
+        )
+    }
+}
+
 /// MySql transaction access mode.
 #[derive(PartialEq, Eq, Clone, Copy, Debug, Hash)]
 #[repr(u8)]
@@ -125,12 +204,35 @@ impl Transaction<'_> {
         Ok(())
     }
 
+    /// Creates a savepoint within this transaction (see [`Savepoint`]).
+    pub fn savepoint(&mut self) -> Result<Savepoint<'_>> {
+        Savepoint::new(&mut *self.conn, 1)
+    }
+
     /// A way to override local infile handler for this transaction.
     /// Destructor of transaction will restore original handler.
     pub fn set_local_infile_handler(&mut self, handler: Option<LocalInfileHandler>) {
         self.conn.set_local_infile_handler(handler);
     }
 
+    /// Redirects to [`crate::Conn::with_query_attributes`].
+    pub fn with_query_attributes<N, V>(&mut self, attributes: &[(N, V)]) -> WithQueryAttributes<'_>
+    where
+        N: AsRef<str>,
+        V: Into<Value> + Clone,
+    {
+        self.conn.with_query_attributes(attributes)
+    }
+
+    /// Redirects to [`crate::Conn::exec_cursor`].
+    pub fn exec_cursor<S, P>(&mut self, stmt: S, params: P, fetch_size: u32) -> Result<Cursor<'_>>
+    where
+        S: AsStatement,
+        P: Into<Params>,
+    {
+        self.conn.exec_cursor(stmt, params, fetch_size)
+    }
+
     /// Returns the number of affected rows, reported by the server.
     pub fn affected_rows(&self) -> u64 {
         self.conn.affected_rows()
@@ -167,6 +269,11 @@ impl Transaction<'_> {
     pub fn info_str(&self) -> Cow<str> {
         self.conn.info_str()
     }
+
+    /// Redirects to [`crate::Conn::session_state`].
+    pub fn session_state(&self) -> &SessionState {
+        self.conn.session_state()
+    }
 }
 
 impl<'a> Queryable for Transaction<'a> {
@@ -191,6 +298,108 @@ impl<'a> Queryable for Transaction<'a> {
     }
 }
 
+/// Savepoint within a transaction (see [`Transaction::savepoint`]).
+///
+/// Savepoint is a nested transaction, i.e. its changes could be rolled back without
+/// rolling back the whole transaction. Savepoints could be nested as well
+/// (see [`Savepoint::savepoint`]).
+///
+/// Savepoint will be rolled back on drop, unless released.
+///
+/// ```rust
+/// # mysql::doctest_wrapper!(__result, {
+/// # use mysql::*;
+/// # use mysql::prelude::*;
+/// # let pool = Pool::new(get_opts())?;
+/// # let mut conn = pool.get_conn()?;
+/// conn.query_drop("CREATE TEMPORARY TABLE mysql.tbl(a INT)")?;
+/// let mut tx = conn.start_transaction(TxOpts::default())?;
+/// tx.query_drop("INSERT INTO mysql.tbl (a) VALUES (1)")?;
+///
+/// let mut sp = tx.savepoint()?;
+/// sp.query_drop("INSERT INTO mysql.tbl (a) VALUES (2)")?;
+/// sp.rollback_to()?;
+///
+/// tx.commit()?;
+/// assert_eq!(conn.query::<u8, _>("SELECT a FROM mysql.tbl")?, vec![1]);
+/// # });
+/// ```
+#[derive(Debug)]
+pub struct Savepoint<'a> {
+    conn: &'a mut Conn,
+    depth: usize,
+    released: bool,
+    rolled_back: bool,
+}
+
+impl<'a> Savepoint<'a> {
+    fn new(conn: &'a mut Conn, depth: usize) -> Result<Savepoint<'a>> {
+        conn.query_drop(format!("SAVEPOINT sp_{}", depth))?;
+        Ok(Savepoint {
+            conn,
+            depth,
+            released: false,
+            rolled_back: false,
+        })
+    }
+
+    /// Creates a savepoint nested into this one.
+    pub fn savepoint(&mut self) -> Result<Savepoint<'_>> {
+        Savepoint::new(&mut *self.conn, self.depth + 1)
+    }
+
+    /// Will consume and release the savepoint, i.e. its changes become
+    /// a part of the enclosing transaction (or savepoint).
+    pub fn release(mut self) -> Result<()> {
+        self.conn
+            .query_drop(format!("RELEASE SAVEPOINT sp_{}", self.depth))?;
+        self.released = true;
+        Ok(())
+    }
+
+    /// Will consume the savepoint and rollback its changes. You also can rely on `Drop`
+    /// implementation but it will swallow errors.
+    pub fn rollback_to(mut self) -> Result<()> {
+        self.conn
+            .query_drop(format!("ROLLBACK TO SAVEPOINT sp_{}", self.depth))?;
+        self.rolled_back = true;
+        Ok(())
+    }
+}
+
+impl Queryable for Savepoint<'_> {
+    fn query_iter<T: AsRef<str>>(&mut self, query: T) -> Result<QueryResult<'_, '_, '_, Text>> {
+        self.conn.query_iter(query)
+    }
+
+    fn prep<T: AsRef<str>>(&mut self, query: T) -> Result<Statement> {
+        self.conn.prep(query)
+    }
+
+    fn close(&mut self, stmt: Statement) -> Result<()> {
+        self.conn.close(stmt)
+    }
+
+    fn exec_iter<S, P>(&mut self, stmt: S, params: P) -> Result<QueryResult<'_, '_, '_, Binary>>
+    where
+        S: AsStatement,
+        P: Into<Params>,
+    {
+        self.conn.exec_iter(stmt, params)
+    }
+}
+
+impl Drop for Savepoint<'_> {
+    /// Will rollback to the savepoint.
+    fn drop(&mut self) {
+        if !self.released && !self.rolled_back {
+            let _ = self
+                .conn
+                .query_drop(format!("ROLLBACK TO SAVEPOINT sp_{}", self.depth));
+        }
+    }
+}
+
 impl<'a> Drop for Transaction<'a> {
     /// Will rollback transaction.
     fn drop(&mut self) {
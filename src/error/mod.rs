@@ -64,6 +64,7 @@ pub enum Error {
     TlsError(tls::TlsError),
     FromValueError(Value),
     FromRowError(Row),
+    CredentialsError(CredentialsError),
 }
 
 impl Error {
@@ -76,7 +77,8 @@ impl Error {
             Error::MySqlError(_)
             | Error::UrlError(_)
             | Error::FromValueError(_)
-            | Error::FromRowError(_) => false,
+            | Error::FromRowError(_)
+            | Error::CredentialsError(_) => false,
         }
     }
 
@@ -98,6 +100,7 @@ impl error::Error for Error {
             Error::UrlError(ref err) => Some(err),
             #[cfg(any(feature = "native-tls", feature = "rustls"))]
             Error::TlsError(ref err) => Some(err),
+            Error::CredentialsError(ref err) => Some(err),
             _ => None,
         }
     }
@@ -159,6 +162,12 @@ impl From<std::convert::Infallible> for Error {
     }
 }
 
+impl From<CredentialsError> for Error {
+    fn from(err: CredentialsError) -> Error {
+        Error::CredentialsError(err)
+    }
+}
+
 impl From<UrlError> for Error {
     fn from(err: UrlError) -> Error {
         Error::UrlError(err)
@@ -183,6 +192,7 @@ impl fmt::Display for Error {
             Error::TlsError(ref err) => write!(f, "TlsError {{ {} }}", err),
             Error::FromRowError(_) => "from row conversion error".fmt(f),
             Error::FromValueError(_) => "from value conversion error".fmt(f),
+            Error::CredentialsError(ref err) => write!(f, "CredentialsError {{ {} }}", err),
         }
     }
 }
@@ -193,6 +203,37 @@ impl fmt::Debug for Error {
     }
 }
 
+/// Error returned by a [`crate::CredentialsProvider`].
+#[derive(Debug)]
+pub struct CredentialsError(Box<dyn error::Error + Send + Sync>);
+
+impl CredentialsError {
+    /// Wraps the given error.
+    pub fn new<E>(err: E) -> Self
+    where
+        E: Into<Box<dyn error::Error + Send + Sync>>,
+    {
+        CredentialsError(err.into())
+    }
+
+    /// Returns the wrapped error.
+    pub fn into_inner(self) -> Box<dyn error::Error + Send + Sync> {
+        self.0
+    }
+}
+
+impl error::Error for CredentialsError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        Some(&*self.0)
+    }
+}
+
+impl fmt::Display for CredentialsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Could not get credentials: {}", self.0)
+    }
+}
+
 #[derive(Eq, PartialEq, Clone)]
 pub enum DriverError {
     ConnectTimeout,
@@ -216,6 +257,14 @@ pub enum DriverError {
     MixedParams,
     UnknownAuthPlugin(String),
     OldMysqlPasswordDisabled,
+    ClearPasswordOverInsecureConnection,
+    InvalidServerPublicKey(String),
+    ServerPublicKeyMismatch,
+    PoolClosed,
+    NoHealthyReplicas,
+    PoolQueueFull,
+    QueryTimeout,
+    AcquireVetoed,
 }
 
 impl error::Error for DriverError {
@@ -279,6 +328,29 @@ impl fmt::Display for DriverError {
                     "`old_mysql_password` plugin is insecure and disabled by default",
                 )
             }
+            DriverError::ClearPasswordOverInsecureConnection => write!(
+                f,
+                "`mysql_clear_password` plugin requires a secure connection (TLS or socket)"
+            ),
+            DriverError::InvalidServerPublicKey(ref path) => {
+                write!(f, "Invalid server public key at `{}'", path)
+            }
+            DriverError::ServerPublicKeyMismatch => write!(
+                f,
+                "Server public key does not match the configured server public key"
+            ),
+            DriverError::PoolClosed => write!(f, "Pool was closed"),
+            DriverError::NoHealthyReplicas => write!(f, "There is no healthy replica"),
+            DriverError::PoolQueueFull => {
+                write!(f, "Too many threads are waiting for a pool connection")
+            }
+            DriverError::QueryTimeout => {
+                write!(f, "Query was killed, because its deadline was exceeded")
+            }
+            DriverError::AcquireVetoed => write!(
+                f,
+                "Pool connections were vetoed by the `before_acquire` hook too many times"
+            ),
         }
     }
 }
@@ -20,6 +20,8 @@ use std::os::{
 };
 use std::{fmt, io, time::Duration};
 
+#[cfg(target_os = "wasi")]
+use std::os::wasi::io::{AsRawFd, RawFd};
 #[cfg(target_os = "wasi")]
 use wasmedge_wasi_socket::{self, SocketAddr};
 
@@ -31,6 +33,18 @@ use crate::error::{
 
 mod tcp;
 mod tls;
+#[cfg(feature = "zstd")]
+mod zstd_stream;
+
+#[cfg(feature = "zstd")]
+pub use self::zstd_stream::ZstdStream;
+
+/// Plain TCP stream of the current target, that TLS streams are built upon.
+#[cfg(not(target_os = "wasi"))]
+pub(crate) type RawTcpStream = net::TcpStream;
+/// Plain TCP stream of the current target, that TLS streams are built upon.
+#[cfg(target_os = "wasi")]
+pub(crate) type RawTcpStream = wasmedge_wasi_socket::TcpStream;
 
 #[derive(Debug, Read, Write)]
 pub enum Stream {
@@ -39,6 +53,8 @@ pub enum Stream {
     #[cfg(windows)]
     SocketStream(BufStream<np::PipeClient>),
     TcpStream(TcpStream),
+    #[cfg(feature = "zstd")]
+    Zstd(Box<ZstdStream>),
 }
 
 impl Stream {
@@ -138,6 +154,8 @@ impl Stream {
     pub fn is_insecure(&self) -> bool {
         match self {
             Stream::TcpStream(TcpStream::Insecure(_)) => true,
+            #[cfg(feature = "zstd")]
+            Stream::Zstd(stream) => stream.get_ref().is_insecure(),
             _ => false,
         }
     }
@@ -146,6 +164,8 @@ impl Stream {
     pub fn is_socket(&self) -> bool {
         match self {
             Stream::SocketStream(_) => true,
+            #[cfg(feature = "zstd")]
+            Stream::Zstd(stream) => stream.get_ref().is_socket(),
             _ => false,
         }
     }
@@ -170,6 +190,19 @@ impl AsRawFd for Stream {
         match self {
             Stream::SocketStream(stream) => stream.get_ref().as_raw_fd(),
             Stream::TcpStream(stream) => stream.as_raw_fd(),
+            #[cfg(feature = "zstd")]
+            Stream::Zstd(stream) => stream.get_ref().as_raw_fd(),
+        }
+    }
+}
+
+#[cfg(target_os = "wasi")]
+impl AsRawFd for Stream {
+    fn as_raw_fd(&self) -> RawFd {
+        match self {
+            Stream::TcpStream(stream) => stream.as_raw_fd(),
+            #[cfg(feature = "zstd")]
+            Stream::Zstd(stream) => stream.get_ref().as_raw_fd(),
         }
     }
 }
@@ -179,14 +212,11 @@ pub enum TcpStream {
     #[cfg(feature = "native-tls")]
     Secure(BufStream<native_tls::TlsStream<net::TcpStream>>),
     #[cfg(feature = "rustls")]
-    Secure(BufStream<rustls::StreamOwned<rustls::ClientConnection, net::TcpStream>>),
-    #[cfg(not(target_os = "wasi"))]
-    Insecure(BufStream<net::TcpStream>),
-    #[cfg(target_os = "wasi")]
-    Insecure(BufStream<wasmedge_wasi_socket::TcpStream>),
+    Secure(BufStream<rustls::StreamOwned<rustls::ClientConnection, RawTcpStream>>),
+    Insecure(BufStream<RawTcpStream>),
 }
 
-#[cfg(unix)]
+#[cfg(any(unix, target_os = "wasi"))]
 impl AsRawFd for TcpStream {
     fn as_raw_fd(&self) -> RawFd {
         match self {
@@ -0,0 +1,161 @@
+// Copyright (c) 2021 Anatoly Ikorsky
+//
+// Licensed under the Apache License, Version 2.0
+// <LICENSE-APACHE or http://www.apache.org/licenses/LICENSE-2.0> or the MIT
+// license <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. All files in the project carrying such notice may not be copied,
+// modified, or distributed except according to those terms.
+
+use std::{
+    cmp,
+    io::{self, Read, Write},
+};
+
+use super::Stream;
+
+/// Length of a compressed packet header.
+const HEADER_LEN: usize = 7;
+
+/// Payloads shorter than this are sent uncompressed (matches the server behavior).
+const MIN_COMPRESS_LENGTH: usize = 50;
+
+/// Stream, that implements zstd compression of the MySql compressed protocol.
+///
+/// Zstd-compressed protocol uses the same framing as the zlib-compressed one, so framing and
+/// sequence ids are handled by the packet codec running with `Compression::none()`, i.e. the
+/// codec reads and writes compressed packets with uncompressed payloads. This stream
+/// transcodes such packets to and from zstd-compressed packets.
+#[derive(Debug)]
+pub struct ZstdStream {
+    stream: Stream,
+    level: i32,
+    /// Decompressed packets, that are not yet consumed.
+    in_buf: Vec<u8>,
+    in_pos: usize,
+    /// Outgoing data, that is not yet a complete packet.
+    out_buf: Vec<u8>,
+}
+
+impl ZstdStream {
+    pub fn new(stream: Stream, level: i32) -> Self {
+        ZstdStream {
+            stream,
+            level,
+            in_buf: Vec::new(),
+            in_pos: 0,
+            out_buf: Vec::new(),
+        }
+    }
+
+    pub fn get_ref(&self) -> &Stream {
+        &self.stream
+    }
+
+    /// Reads the next compressed packet from the wrapped stream into `in_buf`.
+    ///
+    /// Returns `false` if the wrapped stream is at EOF.
+    fn fill_in_buf(&mut self) -> io::Result<bool> {
+        let mut header = [0_u8; HEADER_LEN];
+        let mut read = 0;
+        while read < HEADER_LEN {
+            match self.stream.read(&mut header[read..]) {
+                Ok(0) if read == 0 => return Ok(false),
+                Ok(0) => return Err(io::ErrorKind::UnexpectedEof.into()),
+                Ok(n) => read += n,
+                Err(ref err) if err.kind() == io::ErrorKind::Interrupted => (),
+                Err(err) => return Err(err),
+            }
+        }
+
+        let comp_len = read_u24(&header[..3]);
+        let uncomp_len = read_u24(&header[4..]);
+        let mut payload = vec![0_u8; comp_len];
+        self.stream.read_exact(&mut payload)?;
+
+        self.in_buf.clear();
+        self.in_pos = 0;
+        if uncomp_len == 0 {
+            self.in_buf.extend_from_slice(&header);
+            self.in_buf.extend_from_slice(&payload);
+        } else {
+            let data = zstd::bulk::decompress(&payload, uncomp_len)?;
+            if data.len() != uncomp_len {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "invalid length of a zstd-compressed packet",
+                ));
+            }
+            put_header(&mut self.in_buf, data.len(), header[3], 0);
+            self.in_buf.extend_from_slice(&data);
+        }
+
+        Ok(true)
+    }
+
+    /// Compresses and writes complete packets from `out_buf`.
+    fn write_packets(&mut self) -> io::Result<()> {
+        let mut pos = 0;
+        let mut packets = Vec::new();
+        while self.out_buf.len() - pos >= HEADER_LEN {
+            let header = &self.out_buf[pos..pos + HEADER_LEN];
+            let len = read_u24(&header[..3]);
+            let seq_id = header[3];
+            if self.out_buf.len() - pos - HEADER_LEN < len {
+                break;
+            }
+            let payload = &self.out_buf[pos + HEADER_LEN..pos + HEADER_LEN + len];
+
+            let compressed = if len >= MIN_COMPRESS_LENGTH {
+                Some(zstd::bulk::compress(payload, self.level)?).filter(|x| x.len() < len)
+            } else {
+                None
+            };
+            match compressed {
+                Some(compressed) => {
+                    put_header(&mut packets, compressed.len(), seq_id, len);
+                    packets.extend_from_slice(&compressed);
+                }
+                None => packets.extend_from_slice(&self.out_buf[pos..pos + HEADER_LEN + len]),
+            }
+
+            pos += HEADER_LEN + len;
+        }
+
+        self.out_buf.drain(..pos);
+        self.stream.write_all(&packets)
+    }
+}
+
+impl Read for ZstdStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.in_pos == self.in_buf.len() && !self.fill_in_buf()? {
+            return Ok(0);
+        }
+        let count = cmp::min(buf.len(), self.in_buf.len() - self.in_pos);
+        buf[..count].copy_from_slice(&self.in_buf[self.in_pos..self.in_pos + count]);
+        self.in_pos += count;
+        Ok(count)
+    }
+}
+
+impl Write for ZstdStream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.out_buf.extend_from_slice(buf);
+        self.write_packets()?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.stream.flush()
+    }
+}
+
+fn read_u24(buf: &[u8]) -> usize {
+    buf[0] as usize | (buf[1] as usize) << 8 | (buf[2] as usize) << 16
+}
+
+fn put_header(buf: &mut Vec<u8>, len: usize, seq_id: u8, uncomp_len: usize) {
+    buf.extend_from_slice(&(len as u32).to_le_bytes()[..3]);
+    buf.push(seq_id);
+    buf.extend_from_slice(&(uncomp_len as u32).to_le_bytes()[..3]);
+}